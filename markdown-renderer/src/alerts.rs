@@ -0,0 +1,209 @@
+//! Alert customization: extra types, titles, icons and foldable alerts.
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+
+/// The `[!type]` marker opening a block quote, followed by an optional fold
+/// marker and title.
+static MARKER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[!([A-Za-z][\w-]*)\](.*)$").unwrap());
+
+/// An alert to render in place of comrak's markup.
+struct Alert {
+    /// The lowercase type name, used in the CSS class.
+    kind: String,
+    /// The title written after the marker.
+    title: Option<String>,
+    /// `Some(open)` for a foldable alert.
+    fold: Option<bool>,
+}
+
+/// Whether `options` customizes how alerts are rendered.
+pub(crate) fn customizes_alerts(options: &RenderOptions) -> bool {
+    options.alerts && (options.foldable_alerts || !options.alert_types().is_empty())
+}
+
+/// Render the alerts in the document with the titles and icons set in
+/// `options`, as `<details>` when foldable, and turn block quotes opening with
+/// `[!type]` for a registered type into alerts too.
+///
+/// Each alert is replaced by a raw node holding the opening tags, with the
+/// alert content as its children and a raw closing tag last.
+pub(crate) fn render_alerts<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+) {
+    for node in root.descendants().collect::<Vec<_>>() {
+        let alert = match node.data.borrow().value {
+            NodeValue::Alert(ref alert) => Some(builtin_alert(
+                ast::alert_type_name(alert.alert_type),
+                alert.title.as_deref(),
+                options,
+            )),
+            NodeValue::BlockQuote => None,
+            _ => continue,
+        };
+        let Some(alert) = alert.or_else(|| custom_alert(node, options)) else {
+            continue;
+        };
+
+        let sourcepos = node.data.borrow().sourcepos;
+        let (open_html, close_html) = alert_html(&alert, options);
+        let open_html = match options.sourcepos {
+            true => open_html.replacen('>', &format!(" data-sourcepos=\"{}\">", sourcepos), 1),
+            false => open_html,
+        };
+
+        let wrapper = arena.alloc(NodeValue::Raw(open_html).into());
+        wrapper.data.borrow_mut().sourcepos = sourcepos;
+        node.insert_before(wrapper);
+        for child in node.children().collect::<Vec<_>>() {
+            wrapper.append(child);
+        }
+        wrapper.append(arena.alloc(NodeValue::Raw(close_html).into()));
+        node.detach();
+    }
+}
+
+/// An alert parsed by comrak. Its title holds any fold marker.
+fn builtin_alert(kind: &str, title: Option<&str>, options: &RenderOptions) -> Alert {
+    let (fold, title) = split_fold(title.unwrap_or(""), options);
+
+    Alert {
+        kind: kind.into(),
+        title: Some(title.trim())
+            .filter(|title| !title.is_empty())
+            .map(String::from),
+        fold,
+    }
+}
+
+/// An alert of a registered type written as a block quote, whose marker line
+/// is removed from its first paragraph.
+fn custom_alert<'a>(quote: &'a AstNode<'a>, options: &RenderOptions) -> Option<Alert> {
+    let paragraph = quote
+        .first_child()
+        .filter(|child| matches!(child.data.borrow().value, NodeValue::Paragraph))?;
+
+    let first_line = paragraph
+        .children()
+        .take_while(|node| {
+            !matches!(
+                node.data.borrow().value,
+                NodeValue::SoftBreak | NodeValue::LineBreak
+            )
+        })
+        .collect::<Vec<_>>();
+    let text = first_line
+        .iter()
+        .copied()
+        .map(ast::text_content)
+        .collect::<String>();
+
+    let captures = MARKER_REGEX.captures(&text)?;
+    let kind = captures[1].to_lowercase();
+    if !options.alert_types().contains_key(&kind) {
+        return None;
+    }
+
+    let (fold, title) = split_fold(captures[2].trim_start(), options);
+    let title = title.trim().to_string();
+
+    for node in first_line {
+        node.detach();
+    }
+    if let Some(line_break) = paragraph.first_child() {
+        line_break.detach();
+    }
+    if paragraph.first_child().is_none() {
+        paragraph.detach();
+    }
+
+    Some(Alert {
+        kind,
+        title: Some(title).filter(|title| !title.is_empty()),
+        fold,
+    })
+}
+
+/// Split a leading `-` (collapsed) or `+` (expanded) fold marker off `title`
+/// when foldable alerts are on.
+fn split_fold<'t>(title: &'t str, options: &RenderOptions) -> (Option<bool>, &'t str) {
+    if !options.foldable_alerts {
+        return (None, title);
+    }
+
+    match title.chars().next() {
+        Some('-') => (Some(false), &title[1..]),
+        Some('+') => (Some(true), &title[1..]),
+        _ => (None, title),
+    }
+}
+
+/// The opening and closing HTML of `alert`, matching comrak's alert markup.
+fn alert_html(alert: &Alert, options: &RenderOptions) -> (String, String) {
+    let style = options.alert_types().get(&alert.kind);
+    let icon = style.and_then(|style| style.icon.as_deref()).unwrap_or("");
+    let title = match (&alert.title, style.and_then(|style| style.title.as_ref())) {
+        (Some(title), _) | (None, Some(title)) => ast::escape_html(title),
+        (None, None) => default_title(&alert.kind),
+    };
+    let class = format!("markdown-alert markdown-alert-{}", alert.kind);
+
+    match alert.fold {
+        Some(open) => (
+            format!(
+                "<details class=\"{}\"{}>\n<summary class=\"markdown-alert-title\">{}{}</summary>\n",
+                class,
+                if open { " open" } else { "" },
+                icon,
+                title
+            ),
+            "</details>\n".into(),
+        ),
+        None => (
+            format!(
+                "<div class=\"{}\">\n<p class=\"markdown-alert-title\">{}{}</p>\n",
+                class, icon, title
+            ),
+            "</div>\n".into(),
+        ),
+    }
+}
+
+/// The type name with its first letter capitalized, as comrak titles the
+/// built-in types.
+fn default_title(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_fold() {
+        let mut options = RenderOptions::new();
+        assert_eq!(split_fold("- Title", &options), (None, "- Title"));
+
+        options.foldable_alerts = true;
+        assert_eq!(split_fold("- Title", &options), (Some(false), " Title"));
+        assert_eq!(split_fold("+", &options), (Some(true), ""));
+        assert_eq!(split_fold("Title", &options), (None, "Title"));
+    }
+
+    #[test]
+    fn test_default_title() {
+        assert_eq!(default_title("note"), "Note");
+        assert_eq!(default_title("example"), "Example");
+    }
+}
@@ -0,0 +1,58 @@
+//! Helpers for walking the comrak AST.
+
+use comrak::nodes::{AlertType, AstNode, NodeValue};
+
+/// The arena comrak allocates nodes in.
+pub(crate) type Arena<'a> = typed_arena::Arena<AstNode<'a>>;
+
+/// Concatenate the visible text of `node` and its descendants.
+///
+/// Code spans and math contribute their source, breaks become a single space
+/// and raw HTML is skipped.
+pub(crate) fn text_content<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+
+    for descendant in node.descendants() {
+        match descendant.data.borrow().value {
+            NodeValue::Text(ref literal) => text.push_str(literal),
+            NodeValue::Code(ref code) => text.push_str(&code.literal),
+            NodeValue::Math(ref math) => text.push_str(&math.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// The language of a fenced code block: the first word of its info string.
+pub(crate) fn code_block_lang(info: &str) -> Option<&str> {
+    info.split_whitespace().next()
+}
+
+/// The lowercase name of an alert type, as written in `> [!NOTE]`.
+pub(crate) fn alert_type_name(alert_type: AlertType) -> &'static str {
+    match alert_type {
+        AlertType::Note => "note",
+        AlertType::Tip => "tip",
+        AlertType::Important => "important",
+        AlertType::Warning => "warning",
+        AlertType::Caution => "caution",
+    }
+}
+
+/// Escape `text` for use in HTML text or a double-quoted attribute value.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,282 @@
+//! `:::type [title]` container blocks, as written for VuePress and Docusaurus.
+//!
+//! comrak has no container syntax, so the fence lines are blanked out before
+//! parsing, which splits the contents into ordinary blocks on the same lines,
+//! and the blocks between each pair of fences are moved into a wrapper node
+//! afterwards.
+
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+use crate::streaming::{is_closing_fence, opening_fence};
+
+/// A container fence after any block quote markers and up to three spaces of
+/// indentation, as for code fences: three or more colons, then for an opening
+/// fence the type and an optional title, either bare or in brackets.
+static FENCE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^((?: {0,3}>[ \t]?)* {0,3})(:{3,})[ \t]*(?:([A-Za-z][\w-]*)[ \t]*(?:\[(.*)\]|(.*?)))?[ \t]*$",
+    )
+    .unwrap()
+});
+
+/// Block quote markers at the start of a line. The indentation after them is
+/// left for the code fence checks.
+static PREFIX_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?: {0,3}>[ \t]?)*").unwrap());
+
+/// A container found in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Container {
+    /// The container type, lowercased.
+    pub(crate) kind: String,
+    pub(crate) title: Option<String>,
+    /// 1-based line of the opening fence.
+    pub(crate) open: usize,
+    /// 1-based line and length of the closing fence, `None` if the container
+    /// runs to the end of the document.
+    pub(crate) close: Option<(usize, usize)>,
+}
+
+/// Find the `:::` containers in `markdown` and blank out their fence lines,
+/// keeping any block quote markers so the enclosing quote continues.
+///
+/// Fences inside fenced code blocks are left alone, as are closing fences
+/// without an open container. Containers are returned outermost first.
+pub(crate) fn strip_fences(markdown: &str) -> (Cow<'_, str>, Vec<Container>) {
+    let mut containers: Vec<Container> = Vec::new();
+    // Index into `containers` and fence length of the open containers.
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut code_fence: Option<(char, usize)> = None;
+    let mut stripped = String::with_capacity(markdown.len());
+
+    for (index, line) in markdown.split_inclusive('\n').enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let unprefixed = PREFIX_REGEX.replace(content, "");
+
+        if let Some((c, len)) = code_fence {
+            if is_closing_fence(&unprefixed, c, len) {
+                code_fence = None;
+            }
+            stripped.push_str(line);
+            continue;
+        }
+
+        let Some(captures) = FENCE_REGEX.captures(content) else {
+            code_fence = opening_fence(&unprefixed);
+            stripped.push_str(line);
+            continue;
+        };
+
+        let colons = captures[2].len();
+        match captures.get(3) {
+            Some(kind) => {
+                let title = captures
+                    .get(4)
+                    .or(captures.get(5))
+                    .map(|t| t.as_str().trim());
+                containers.push(Container {
+                    kind: kind.as_str().to_lowercase(),
+                    title: title.filter(|t| !t.is_empty()).map(String::from),
+                    open: index + 1,
+                    close: None,
+                });
+                open.push((containers.len() - 1, colons));
+            }
+            None => match open.last() {
+                Some(&(container, len)) if colons >= len => {
+                    containers[container].close = Some((index + 1, content.chars().count()));
+                    open.pop();
+                }
+                _ => {
+                    stripped.push_str(line);
+                    continue;
+                }
+            },
+        }
+
+        stripped.push_str(captures[1].trim_end());
+        stripped.push_str(&line[content.len()..]);
+    }
+
+    match containers.is_empty() {
+        true => (Cow::Borrowed(markdown), containers),
+        false => (Cow::Owned(stripped), containers),
+    }
+}
+
+/// Move the blocks of each container into a wrapper rendering the container
+/// element around them.
+///
+/// The wrapper is a raw node holding the opening tags, with the blocks as its
+/// children and a raw closing tag last, so the whole container stays a single
+/// block for incremental and streaming rendering.
+pub(crate) fn wrap_containers<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    containers: &[Container],
+    options: &RenderOptions,
+) {
+    for container in containers {
+        let parent = enclosing_block(root, container.open);
+        let inside = parent
+            .children()
+            .filter(|child| {
+                let line = child.data.borrow().sourcepos.start.line;
+                line > container.open && container.close.is_none_or(|(close, _)| line < close)
+            })
+            .collect::<Vec<_>>();
+
+        let end = match container.close {
+            Some(close) => close,
+            None => inside.last().map_or((container.open, 1), |last| {
+                let end = last.data.borrow().sourcepos.end;
+                (end.line, end.column)
+            }),
+        };
+        let sourcepos: Sourcepos = (container.open, 1, end.0, end.1).into();
+
+        let (open_html, close_html) = container_html(container, options);
+        let open_html = match options.sourcepos {
+            true => open_html.replacen('>', &format!(" data-sourcepos=\"{}\">", sourcepos), 1),
+            false => open_html,
+        };
+
+        let wrapper = arena.alloc(NodeValue::Raw(open_html).into());
+        wrapper.data.borrow_mut().sourcepos = sourcepos;
+        match parent
+            .children()
+            .find(|child| child.data.borrow().sourcepos.start.line > container.open)
+        {
+            Some(next) => next.insert_before(wrapper),
+            None => parent.append(wrapper),
+        }
+
+        for child in inside {
+            wrapper.append(child);
+        }
+        wrapper.append(arena.alloc(NodeValue::Raw(close_html).into()));
+    }
+}
+
+/// The deepest block containing `line`, whose children are the container's
+/// siblings.
+fn enclosing_block<'a>(root: &'a AstNode<'a>, line: usize) -> &'a AstNode<'a> {
+    let mut parent = root;
+    while let Some(child) = parent.children().find(|child| {
+        let sourcepos = child.data.borrow().sourcepos;
+        sourcepos.start.line <= line && line <= sourcepos.end.line
+    }) {
+        parent = child;
+    }
+
+    parent
+}
+
+/// The opening and closing HTML of `container`.
+///
+/// `details` becomes a `<details>` with the title as its summary, other types
+/// on [`RenderOptions::container_types`] a `<div class="container container-type">`,
+/// and anything else a plain `<div>`.
+fn container_html(container: &Container, options: &RenderOptions) -> (String, String) {
+    let title = container.title.as_deref().map(ast::escape_html);
+    let known = options
+        .container_types
+        .iter()
+        .any(|kind| kind.eq_ignore_ascii_case(&container.kind));
+
+    if known && container.kind == "details" {
+        return (
+            format!(
+                "<details class=\"container container-details\">\n<summary>{}</summary>\n",
+                title.as_deref().unwrap_or("Details")
+            ),
+            "</details>\n".into(),
+        );
+    }
+
+    let tag = match known {
+        true => format!("<div class=\"container container-{}\">", container.kind),
+        false => "<div>".into(),
+    };
+    let open = match title {
+        Some(title) => format!("{}\n<p class=\"container-title\">{}</p>\n", tag, title),
+        None => format!("{}\n", tag),
+    };
+
+    (open, "</div>\n".into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_fences() {
+        let markdown = concat!(
+            "::: tip Read this\n",
+            "Text\n",
+            ":::: details\n",
+            "```\n",
+            ":::\n",
+            "```\n",
+            "::::\n",
+            "> :::note[Quoted title]\n",
+            "> Quote\n",
+            "> :::\n",
+            ":::\n",
+        );
+        let (stripped, containers) = strip_fences(markdown);
+
+        assert_eq!(stripped, "\nText\n\n```\n:::\n```\n\n>\n> Quote\n>\n\n");
+        assert_eq!(
+            containers,
+            vec![
+                Container {
+                    kind: "tip".into(),
+                    title: Some("Read this".into()),
+                    open: 1,
+                    close: Some((11, 3)),
+                },
+                Container {
+                    kind: "details".into(),
+                    title: None,
+                    open: 3,
+                    close: Some((7, 4)),
+                },
+                Container {
+                    kind: "note".into(),
+                    title: Some("Quoted title".into()),
+                    open: 8,
+                    close: Some((10, 5)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_fences_unmatched() {
+        let (stripped, containers) = strip_fences("Text\n:::\n");
+        assert_eq!(stripped, "Text\n:::\n");
+        assert!(containers.is_empty());
+
+        let (_, containers) = strip_fences("::: warning\nUnclosed\n");
+        assert_eq!(containers[0].close, None);
+    }
+
+    #[test]
+    fn test_strip_fences_indentation() {
+        let markdown = "Text\n\n    ::: note\n    code\n    :::\n";
+        let (stripped, containers) = strip_fences(markdown);
+        assert_eq!(stripped, markdown);
+        assert!(containers.is_empty());
+
+        let (stripped, containers) = strip_fences("   ::: tip\nText\n   :::\n");
+        assert_eq!(stripped, "\nText\n\n");
+        assert_eq!(containers[0].close, Some((3, 6)));
+    }
+}
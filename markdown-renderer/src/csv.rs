@@ -0,0 +1,276 @@
+//! ```` ```csv ```` and ```` ```tsv ```` fences rendered as tables.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+use crate::render::Diagnostic;
+use crate::tables;
+
+/// How a CSV block is read, from its info string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Format {
+    delimiter: char,
+    /// The first row is the header.
+    header: bool,
+}
+
+/// Render the `csv` and `tsv` code blocks in the document as tables, with
+/// `header=false` and `delimiter=;` flags in the info string after the
+/// language. Blocks that don't parse are left as highlighted code and
+/// reported as warnings.
+pub(crate) fn render_csv_tables<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+
+    for node in root.descendants().collect::<Vec<_>>() {
+        let (format, literal) = match node.data.borrow().value {
+            NodeValue::CodeBlock(ref block) if block.fenced => match parse_info(&block.info) {
+                Some(format) => (format, block.literal.clone()),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let sourcepos = node.data.borrow().sourcepos;
+
+        let rows = match parse_csv(&literal, format.delimiter) {
+            Ok(rows) if !rows.is_empty() => rows,
+            Ok(_) => continue,
+            Err((line, message)) => {
+                warnings.push(Diagnostic {
+                    line: sourcepos.start.line + line,
+                    message: format!("CSV block left as code: {}", message),
+                });
+                continue;
+            }
+        };
+
+        let html = match options.sourcepos {
+            true => table_html(&rows, format.header, options).replacen(
+                "<table>",
+                &format!("<table data-sourcepos=\"{}\">", sourcepos),
+                1,
+            ),
+            false => table_html(&rows, format.header, options),
+        };
+        let raw = arena.alloc(NodeValue::Raw(html).into());
+        raw.data.borrow_mut().sourcepos = sourcepos;
+        node.insert_before(raw);
+        node.detach();
+    }
+
+    warnings
+}
+
+/// The format of a code block with info string `info`, if it is CSV or TSV.
+fn parse_info(info: &str) -> Option<Format> {
+    let mut words = info.split_whitespace();
+    let mut format = match words.next()? {
+        "csv" => Format {
+            delimiter: ',',
+            header: true,
+        },
+        "tsv" => Format {
+            delimiter: '\t',
+            header: true,
+        },
+        _ => return None,
+    };
+
+    for flag in words {
+        match flag.split_once('=') {
+            Some(("header", value)) => format.header = value != "false",
+            Some(("delimiter", "tab" | "\\t")) => format.delimiter = '\t',
+            Some(("delimiter", value)) => {
+                let mut chars = value.chars();
+                if let (Some(delimiter), None) = (chars.next(), chars.next()) {
+                    format.delimiter = delimiter;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(format)
+}
+
+/// Split `source` into rows of fields, as in RFC 4180: fields may be quoted
+/// with `"`, a quoted field may hold delimiters, newlines and `""` for a quote,
+/// and blank lines are skipped.
+///
+/// Errors carry the 1-based line in `source` they were found on.
+fn parse_csv(source: &str, delimiter: char) -> Result<Vec<Vec<String>>, (usize, String)> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    // The line a quoted field being read started on.
+    let mut quoted: Option<usize> = None;
+    let mut after_quote = false;
+
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted.is_some() {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => {
+                    quoted = None;
+                    after_quote = true;
+                }
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() && !after_quote => quoted = Some(line),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() || after_quote {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+                after_quote = false;
+                line += 1;
+            }
+            _ if c == delimiter => {
+                row.push(std::mem::take(&mut field));
+                after_quote = false;
+            }
+            _ if after_quote => {
+                return Err((line, format!("unexpected `{}` after a closing quote", c)));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if let Some(start) = quoted {
+        return Err((start, "quoted field is never closed".into()));
+    }
+    if !row.is_empty() || !field.is_empty() || after_quote {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// A table of `rows`, padded to the widest row. With [`RenderOptions::table_wrappers`]
+/// it is wrapped and sort hinted like the Markdown tables.
+fn table_html(rows: &[Vec<String>], header: bool, options: &RenderOptions) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let (head, body) = match header {
+        true => rows.split_at(1),
+        false => rows.split_at(0),
+    };
+
+    let mut html = String::from("<table>\n");
+    if let Some(head) = head.first() {
+        html.push_str("<thead>\n<tr>\n");
+        for column in 0..columns {
+            let cell = head.get(column).map_or("", String::as_str);
+            match options.table_wrappers {
+                true => {
+                    let values = body
+                        .iter()
+                        .filter_map(|row| row.get(column))
+                        .map(|value| value.trim())
+                        .filter(|value| !value.is_empty())
+                        .collect::<Vec<_>>();
+                    html.push_str(&format!(
+                        "<th data-sort-type=\"{}\">",
+                        tables::sort_type_of(&values)
+                    ));
+                }
+                false => html.push_str("<th>"),
+            }
+            html.push_str(&ast::escape_html(cell));
+            html.push_str("</th>\n");
+        }
+        html.push_str("</tr>\n</thead>\n");
+    }
+    if !body.is_empty() {
+        html.push_str("<tbody>\n");
+        for row in body {
+            html.push_str("<tr>\n");
+            for column in 0..columns {
+                let cell = row.get(column).map_or("", String::as_str);
+                html.push_str(&format!("<td>{}</td>\n", ast::escape_html(cell)));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody>\n");
+    }
+    html.push_str("</table>\n");
+
+    match options.table_wrappers {
+        true => format!("<div class=\"table-wrapper\">\n{}</div>\n", html),
+        false => html,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|field| field.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_info() {
+        assert_eq!(
+            parse_info("csv"),
+            Some(Format {
+                delimiter: ',',
+                header: true
+            })
+        );
+        assert_eq!(
+            parse_info("csv header=false delimiter=;"),
+            Some(Format {
+                delimiter: ';',
+                header: false
+            })
+        );
+        assert_eq!(
+            parse_info("tsv title=x"),
+            Some(Format {
+                delimiter: '\t',
+                header: true
+            })
+        );
+        assert_eq!(parse_info("rust"), None);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(
+            parse_csv("a,b\r\n1,\"x, \"\"y\"\"\nz\"\n\n2,\n", ','),
+            Ok(rows(&[&["a", "b"], &["1", "x, \"y\"\nz"], &["2", ""]]))
+        );
+        assert_eq!(parse_csv("a;\"\"", ';'), Ok(rows(&[&["a", ""]])));
+        assert_eq!(
+            parse_csv("a,b\n\"c\"d,e\n", ','),
+            Err((2, "unexpected `d` after a closing quote".into()))
+        );
+        assert_eq!(
+            parse_csv("a\n\"b,\nc\n", ','),
+            Err((2, "quoted field is never closed".into()))
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! `:shortcode:` emoji, as Unicode or as images.
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+
+/// A GitHub-style shortcode such as `:tada:` or `:+1:`.
+static SHORTCODE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r":([a-z0-9_+-]+):").unwrap());
+
+/// Replace the known shortcodes in the document's text with emoji.
+///
+/// With [`RenderOptions::emoji_image_url_template`] set, each emoji becomes
+/// an `<img class="emoji">` instead, except in image alt text. Only text nodes
+/// are searched, so code spans, code blocks and math keep their shortcodes.
+pub(crate) fn replace_shortcodes<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+) {
+    for node in root.descendants().collect::<Vec<_>>() {
+        let text = match node.data.borrow().value {
+            NodeValue::Text(ref text) if text.contains(':') => text.to_string(),
+            _ => continue,
+        };
+        let template = options
+            .emoji_image_url_template
+            .as_ref()
+            .filter(|_| !in_image(node));
+
+        let mut rest = 0;
+        let mut replaced = String::new();
+        for (shortcode, emoji) in shortcodes(&text) {
+            replaced.push_str(&text[rest..shortcode.start]);
+            rest = shortcode.end;
+
+            match template {
+                Some(template) => {
+                    let img = emoji_image(template, emoji, &text[shortcode]);
+                    insert_text(arena, node, &std::mem::take(&mut replaced));
+                    node.insert_before(arena.alloc(NodeValue::Raw(img).into()));
+                }
+                None => replaced.push_str(emoji.as_str()),
+            }
+        }
+
+        if rest > 0
+            && let NodeValue::Text(ref mut literal) = node.data.borrow_mut().value
+        {
+            replaced.push_str(&text[rest..]);
+            *literal = replaced.into();
+        }
+    }
+}
+
+/// The known shortcodes in `text` with their byte ranges. A `:word:` that isn't
+/// an emoji is skipped from its closing colon, which may open the next
+/// shortcode as in `:time:tada:`.
+fn shortcodes(text: &str) -> Vec<(Range<usize>, &'static emojis::Emoji)> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(captures) = SHORTCODE_REGEX.captures_at(text, pos) {
+        let shortcode = captures.get(0).unwrap();
+        match emojis::get_by_shortcode(&captures[1]) {
+            Some(emoji) => {
+                found.push((shortcode.range(), emoji));
+                pos = shortcode.end();
+            }
+            None => pos = shortcode.end() - 1,
+        }
+    }
+    found
+}
+
+fn insert_text<'a>(arena: &'a Arena<'a>, node: &'a AstNode<'a>, text: &str) {
+    if !text.is_empty() {
+        node.insert_before(arena.alloc(NodeValue::Text(text.to_string().into()).into()));
+    }
+}
+
+fn in_image<'a>(node: &'a AstNode<'a>) -> bool {
+    node.ancestors()
+        .any(|ancestor| matches!(ancestor.data.borrow().value, NodeValue::Image(..)))
+}
+
+/// An `<img>` for `emoji`, with `{codepoints}` and `{shortcode}` in `template`
+/// replaced. Codepoints are lowercase hex joined by `-`, without variation
+/// selectors, as used by Twemoji and most emoji CDNs.
+fn emoji_image(template: &str, emoji: &emojis::Emoji, shortcode: &str) -> String {
+    let codepoints = emoji
+        .as_str()
+        .chars()
+        .filter(|c| *c != '\u{FE0F}')
+        .map(|c| format!("{:x}", c as u32))
+        .collect::<Vec<String>>()
+        .join("-");
+    let src = template
+        .replace("{codepoints}", &codepoints)
+        .replace("{shortcode}", shortcode.trim_matches(':'));
+
+    format!(
+        "<img class=\"emoji\" src=\"{}\" alt=\"{}\" title=\"{}\" />",
+        ast::escape_html(&src),
+        emoji.as_str(),
+        shortcode
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_emoji_image() {
+        let tada = emojis::get_by_shortcode("tada").unwrap();
+        assert_eq!(
+            emoji_image("https://cdn.example.com/{codepoints}.svg", tada, ":tada:"),
+            "<img class=\"emoji\" src=\"https://cdn.example.com/1f389.svg\" alt=\"🎉\" title=\":tada:\" />"
+        );
+
+        let heart = emojis::get_by_shortcode("heart").unwrap();
+        assert_eq!(
+            emoji_image("/emoji/{shortcode}-{codepoints}.png", heart, ":heart:"),
+            "<img class=\"emoji\" src=\"/emoji/heart-2764.png\" alt=\"❤️\" title=\":heart:\" />"
+        );
+    }
+
+    #[test]
+    fn test_shortcodes() {
+        let ranges = |text: &str| {
+            shortcodes(text)
+                .into_iter()
+                .map(|(range, emoji)| (range, emoji.as_str()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges(":time:tada:"), vec![(5..11, "🎉")]);
+        assert_eq!(ranges(":tada::+1:"), vec![(0..6, "🎉"), (6..10, "👍")]);
+        assert_eq!(ranges(":no:such:"), vec![]);
+    }
+}
@@ -0,0 +1,297 @@
+//! Excerpts: the start of a document, cut on the AST so the rendered HTML
+//! stays balanced.
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::sync::LazyLock;
+use wasm_bindgen::prelude::*;
+
+use crate::ast::Arena;
+
+/// Appended to the last kept block when an excerpt is cut short.
+const ELLIPSIS: &str = "…";
+
+/// Elements dropped from excerpts by [`ExcerptLimit::drop_media`].
+const MEDIA_TAGS: [&str; 6] = ["img", "iframe", "video", "audio", "picture", "source"];
+
+/// HTML elements without a closing tag.
+const VOID_TAGS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// An opening or closing HTML tag: the `/` of a closing tag, the tag name and
+/// the `/` of a self-closing tag.
+static HTML_TAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/)?([A-Za-z][A-Za-z0-9-]*)(?:\s[^>]*?)?(/)?>").unwrap());
+
+/// How much of a document [`crate::render_excerpt`] keeps.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExcerptLimit {
+    /// Maximum number of visible text characters, code and math included.
+    pub chars: Option<usize>,
+    /// Maximum number of top-level blocks.
+    pub blocks: Option<usize>,
+    /// Leave out images, iframes and other embedded media.
+    pub drop_media: bool,
+}
+
+#[wasm_bindgen]
+impl ExcerptLimit {
+    /// No limits; set `chars` and/or `blocks` to cut the document.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+/// Cut the document rooted at `root` down to `limit`, ending it with "…" if
+/// anything was left out. Footnote definitions are always left out, and so
+/// are the references to them.
+pub(crate) fn truncate<'a>(arena: &'a Arena<'a>, root: &'a AstNode<'a>, limit: &ExcerptLimit) {
+    for node in root.descendants().collect::<Vec<_>>() {
+        if matches!(
+            node.data.borrow().value,
+            NodeValue::FootnoteDefinition(..) | NodeValue::FootnoteReference(..)
+        ) {
+            node.detach();
+        }
+    }
+
+    if limit.drop_media {
+        drop_media(root);
+        remove_empty(root);
+    }
+
+    let mut truncated = false;
+    if let Some(max) = limit.blocks {
+        let blocks = root
+            .children()
+            .filter(|node| !matches!(node.data.borrow().value, NodeValue::FrontMatter(..)))
+            .collect::<Vec<_>>();
+        for block in blocks.iter().skip(max) {
+            block.detach();
+            truncated = true;
+        }
+    }
+    if let Some(max) = limit.chars {
+        truncated |= truncate_chars(root, max);
+    }
+
+    if truncated {
+        drop_unclosed_html(root);
+    }
+    remove_empty(root);
+    if truncated {
+        add_ellipsis(arena, root);
+    }
+}
+
+/// Cut the document after `max` characters of text. Returns whether anything
+/// was cut.
+fn truncate_chars<'a>(root: &'a AstNode<'a>, max: usize) -> bool {
+    let mut remaining = max;
+
+    for node in root.descendants().collect::<Vec<_>>() {
+        let len = match node.data.borrow().value {
+            NodeValue::Text(ref text) => text.chars().count(),
+            NodeValue::Code(ref code) => code.literal.chars().count(),
+            NodeValue::Math(ref math) => math.literal.chars().count(),
+            NodeValue::CodeBlock(ref block) => block.literal.chars().count(),
+            NodeValue::SoftBreak | NodeValue::LineBreak => 1,
+            _ => continue,
+        };
+        if len <= remaining {
+            remaining -= len;
+            continue;
+        }
+
+        let keep = match node.data.borrow_mut().value {
+            NodeValue::Text(ref mut text) => {
+                let cut = cut_text(text, remaining).to_string();
+                *text = cut.into();
+                !text.is_empty()
+            }
+            NodeValue::CodeBlock(ref mut block) => {
+                let mut kept = 0;
+                let lines = block
+                    .literal
+                    .split_inclusive('\n')
+                    .take_while(|line| {
+                        kept += line.chars().count();
+                        kept <= remaining
+                    })
+                    .collect::<String>();
+                block.literal = lines;
+                !block.literal.is_empty()
+            }
+            _ => false,
+        };
+
+        // Raw inline HTML before the cut may open tags closed after it.
+        if let Some(block) = top_level_block(root, node) {
+            strip_inline_html(block);
+        }
+        detach_following(node);
+        if !keep {
+            node.detach();
+        }
+        return true;
+    }
+
+    false
+}
+
+/// The first `chars` characters of `text`, backed up to a word boundary when
+/// the cut falls inside a word.
+fn cut_text(text: &str, chars: usize) -> &str {
+    let end = text
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| i);
+    let cut = &text[..end];
+
+    let mid_word = end < text.len() && !text[end..].starts_with(char::is_whitespace);
+    match cut.rfind(char::is_whitespace) {
+        Some(space) if mid_word => cut[..space].trim_end(),
+        _ => cut.trim_end(),
+    }
+}
+
+/// Detach everything after `node` in document order.
+fn detach_following<'a>(node: &'a AstNode<'a>) {
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        while let Some(next) = current.next_sibling() {
+            next.detach();
+        }
+        current = parent;
+    }
+}
+
+fn top_level_block<'a>(root: &'a AstNode<'a>, node: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
+    node.ancestors().find(|ancestor| {
+        ancestor
+            .parent()
+            .is_some_and(|parent| parent.same_node(root))
+    })
+}
+
+fn strip_inline_html<'a>(block: &'a AstNode<'a>) {
+    for node in block.descendants().collect::<Vec<_>>() {
+        if matches!(node.data.borrow().value, NodeValue::HtmlInline(..)) {
+            node.detach();
+        }
+    }
+}
+
+/// Detach the HTML blocks opening a tag that no later HTML block closes, such
+/// as a `<details>` whose `</details>` was cut. The Markdown between them is
+/// kept.
+fn drop_unclosed_html<'a>(root: &'a AstNode<'a>) {
+    let mut open: Vec<(String, &'a AstNode<'a>)> = Vec::new();
+
+    for node in root.descendants() {
+        let NodeValue::HtmlBlock(ref block) = node.data.borrow().value else {
+            continue;
+        };
+        for tag in HTML_TAG_REGEX.captures_iter(&block.literal) {
+            let name = tag[2].to_ascii_lowercase();
+            if tag.get(1).is_some() {
+                // Tags left open inside the closed one end with it.
+                if let Some(i) = open.iter().rposition(|(open, _)| *open == name) {
+                    open.truncate(i);
+                }
+            } else if tag.get(3).is_none() && !VOID_TAGS.contains(&name.as_str()) {
+                open.push((name, node));
+            }
+        }
+    }
+
+    for (_, node) in open {
+        node.detach();
+    }
+}
+
+fn drop_media<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants().collect::<Vec<_>>() {
+        let is_media = match node.data.borrow().value {
+            NodeValue::Image(..) => true,
+            NodeValue::HtmlBlock(ref block) => contains_media(&block.literal),
+            NodeValue::HtmlInline(ref html) => contains_media(html),
+            _ => false,
+        };
+
+        if is_media {
+            node.detach();
+        }
+    }
+}
+
+fn contains_media(html: &str) -> bool {
+    let html = html.to_ascii_lowercase();
+    MEDIA_TAGS
+        .iter()
+        .any(|tag| html.contains(&format!("<{}", tag)) || html.contains(&format!("</{}", tag)))
+}
+
+/// Remove containers left empty by cutting or dropping media.
+fn remove_empty<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants().collect::<Vec<_>>().into_iter().rev() {
+        let container = matches!(
+            node.data.borrow().value,
+            NodeValue::Paragraph
+                | NodeValue::Heading(..)
+                | NodeValue::Link(..)
+                | NodeValue::BlockQuote
+                | NodeValue::List(..)
+                | NodeValue::Item(..)
+                | NodeValue::TaskItem(..)
+        );
+
+        if container && node.first_child().is_none() {
+            node.detach();
+        }
+    }
+}
+
+/// Append the ellipsis to the last paragraph or heading, descending into
+/// quotes and lists, or add it as its own paragraph.
+fn add_ellipsis<'a>(arena: &'a Arena<'a>, root: &'a AstNode<'a>) {
+    let ellipsis = arena.alloc(NodeValue::Text(ELLIPSIS.into()).into());
+    let mut target = root;
+
+    while let Some(last) = target.last_child() {
+        match last.data.borrow().value {
+            NodeValue::Paragraph | NodeValue::Heading(..) => {
+                last.append(ellipsis);
+                return;
+            }
+            NodeValue::BlockQuote
+            | NodeValue::Alert(..)
+            | NodeValue::List(..)
+            | NodeValue::Item(..)
+            | NodeValue::TaskItem(..) => target = last,
+            _ => break,
+        }
+    }
+
+    let paragraph = arena.alloc(NodeValue::Paragraph.into());
+    paragraph.append(ellipsis);
+    root.append(paragraph);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cut_text_backs_up_to_word_boundary() {
+        assert_eq!(cut_text("bold world", 6), "bold");
+        assert_eq!(cut_text("bold world", 5), "bold");
+        assert_eq!(cut_text("bold world", 99), "bold world");
+        assert_eq!(cut_text("überlang", 4), "über");
+        assert_eq!(cut_text("word", 0), "");
+    }
+}
@@ -0,0 +1,91 @@
+//! Footnote references with hover-preview content.
+
+use comrak::nodes::{AstNode, NodeValue};
+use std::collections::HashMap;
+
+use crate::ast::{self, Arena};
+use crate::plain_text;
+
+/// Give every footnote reference a `data-footnote-content` attribute holding
+/// the plain text of its footnote, so previews don't need the footnotes section.
+///
+/// comrak has no hook for reference attributes, so each reference is replaced
+/// by raw HTML with the same markup comrak writes plus the preview.
+pub(crate) fn add_previews<'a>(arena: &'a Arena<'a>, root: &'a AstNode<'a>) {
+    // comrak gives each reference the name of the definition it resolved to.
+    let previews = root
+        .children()
+        .filter_map(|node| match node.data.borrow().value {
+            NodeValue::FootnoteDefinition(ref definition) => {
+                Some((definition.name.to_string(), plain_text::plain_text(node)))
+            }
+            _ => None,
+        })
+        .collect::<HashMap<String, String>>();
+
+    for node in root.descendants().collect::<Vec<_>>() {
+        let html = match node.data.borrow().value {
+            NodeValue::FootnoteReference(ref reference) => {
+                let preview = previews.get(&*reference.name).map_or("", String::as_str);
+                reference_html(&reference.name, reference.ref_num, reference.ix, preview)
+            }
+            _ => continue,
+        };
+
+        let raw = arena.alloc(NodeValue::Raw(html).into());
+        raw.data.borrow_mut().sourcepos = node.data.borrow().sourcepos;
+        node.insert_before(raw);
+        node.detach();
+    }
+}
+
+/// The reference markup comrak writes, with `data-footnote-content` added.
+fn reference_html(name: &str, ref_num: u32, ix: u32, preview: &str) -> String {
+    let mut id = format!("fnref-{}", escape_href(name));
+    if ref_num > 1 {
+        id = format!("{}-{}", id, ref_num);
+    }
+
+    format!(
+        "<sup class=\"footnote-ref\"><a href=\"#fn-{}\" id=\"{}\" data-footnote-ref data-footnote-content=\"{}\">{}</a></sup>",
+        escape_href(name),
+        id,
+        ast::escape_html(preview.trim()),
+        ix
+    )
+}
+
+/// Escape a footnote name the way comrak does for the `fn-` and `fnref-` ids,
+/// so the references keep matching the footnotes section.
+fn escape_href(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'&' => escaped.push_str("&amp;"),
+            b'\'' => escaped.push_str("&#x27;"),
+            _ if byte.is_ascii_alphanumeric() || b"-_.+!*(),%#@?=;:/$~".contains(&byte) => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reference_html() {
+        assert_eq!(
+            reference_html("note", 2, 1, "A \"quoted\" note.\n"),
+            concat!(
+                "<sup class=\"footnote-ref\"><a href=\"#fn-note\" id=\"fnref-note-2\" ",
+                "data-footnote-ref data-footnote-content=\"A &quot;quoted&quot; note.\">1</a></sup>",
+            )
+        );
+        assert_eq!(escape_href("my note&é"), "my%20note&amp;%C3%A9");
+    }
+}
@@ -0,0 +1,98 @@
+//! Front matter parsing.
+//!
+//! comrak only strips the front matter block from the output, so the block it
+//! hands back is parsed here into a JSON value. YAML (`---`) and TOML (`+++`)
+//! are supported.
+
+use serde_json::{Map, Value};
+
+pub(crate) const YAML_DELIMITER: &str = "---";
+pub(crate) const TOML_DELIMITER: &str = "+++";
+
+/// Whether `markdown` opens with a TOML front matter block.
+pub(crate) fn starts_with_toml(markdown: &str) -> bool {
+    markdown
+        .lines()
+        .next()
+        .is_some_and(|line| line.trim_end() == TOML_DELIMITER)
+}
+
+/// Strip the delimiter lines and surrounding blank lines from a front matter block.
+pub(crate) fn strip_delimiters(raw: &str) -> &str {
+    let raw = raw.trim();
+    let mut lines = raw.lines();
+    let (Some(first), Some(last)) = (lines.next(), lines.next_back()) else {
+        return "";
+    };
+
+    raw[first.len()..raw.len() - last.len()].trim_matches(['\r', '\n'])
+}
+
+/// Parse a front matter block, delimiters included, into a JSON value.
+///
+/// The format is picked from the opening delimiter. An empty block parses to
+/// an empty object.
+pub(crate) fn parse(raw: &str) -> Result<Value, String> {
+    let body = strip_delimiters(raw);
+    if body.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+
+    if raw.trim_start().starts_with(TOML_DELIMITER) {
+        toml::from_str::<toml::Table>(body)
+            .map(|table| toml_to_json(toml::Value::Table(table)))
+            .map_err(|e| format!("invalid TOML front matter: {}", e.message()))
+    } else {
+        serde_norway::from_str::<Value>(body)
+            .map_err(|e| format!("invalid YAML front matter: {}", e))
+    }
+}
+
+/// Convert a TOML value to JSON. Datetimes become their RFC 3339 strings.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_yaml() {
+        let raw = "---\ntitle: Docs\ntags: [a, b]\nauthor:\n  name: Ann\n---\n\n";
+        assert_eq!(
+            parse(raw).unwrap(),
+            json!({ "title": "Docs", "tags": ["a", "b"], "author": { "name": "Ann" } })
+        );
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let raw = "+++\ntitle = \"Docs\"\ntags = [\"a\", \"b\"]\ndate = 2024-01-02\n+++\n";
+        assert_eq!(
+            parse(raw).unwrap(),
+            json!({ "title": "Docs", "tags": ["a", "b"], "date": "2024-01-02" })
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_and_invalid() {
+        assert_eq!(parse("---\n---\n").unwrap(), json!({}));
+        assert!(parse("---\ntitle: [unclosed\n---\n").is_err());
+        assert!(parse("+++\ntitle = \n+++\n").is_err());
+    }
+}
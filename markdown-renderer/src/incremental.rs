@@ -0,0 +1,1038 @@
+//! Incremental re-rendering for live preview.
+//!
+//! The document is split into its top-level blocks, each identified by a key
+//! derived from its source and from the document-wide state its HTML depends
+//! on. Only blocks whose key is new get rendered. Everything else reuses the
+//! HTML from the previous update, and the caller receives a patch list instead
+//! of the whole document.
+//!
+//! An edit re-parses only the blocks around the changed lines, with the
+//! document's link reference and footnote definitions added so references
+//! still resolve. The whole document is parsed again when the edit changes
+//! what other blocks read from it: link reference definitions, footnote
+//! definitions or references, headings when their slugs are used, or the
+//! number of interactive tasks.
+
+use comrak::Options;
+use comrak::nodes::{AstNode, NodeValue};
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+use wasm_bindgen::prelude::*;
+
+use crate::ast::Arena;
+use crate::links::{self, DefinitionRun};
+use crate::render::{self, Heading};
+use crate::toc::TocEntry;
+use crate::{PLUGINS, RenderOptions, Themes, front_matter, tasks};
+
+/// The source lines written into the HTML: `data-sourcepos` and the
+/// `data-task-line` of interactive tasks.
+static LINE_ATTR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(data-sourcepos=")(\d+)(:\d+-)(\d+)(:\d+")|(data-task-line=")(\d+)(")"#).unwrap()
+});
+
+/// A rendered top-level block.
+#[derive(Debug, Clone)]
+struct Block {
+    key: String,
+    html: String,
+    /// Source line ranges. The footnote definitions are one block, with a
+    /// range for each.
+    ranges: Vec<RangeInclusive<usize>>,
+    /// Whether this is the footnotes section.
+    footnotes: bool,
+    /// Hash of what the HTML depends on, apart from the block's position.
+    hash: u64,
+    /// The first source line, when the HTML holds source lines.
+    position: Option<usize>,
+    state: BlockState,
+}
+
+impl Block {
+    /// The block HTML inside a wrapper the caller can find again by key.
+    fn wrapped_html(&self) -> String {
+        format!(
+            "<div class=\"md-block\" data-block-key=\"{}\">{}</div>",
+            self.key, self.html
+        )
+    }
+
+    /// The block with its ranges after line `after` moved by `delta` lines.
+    fn shifted(&self, after: usize, delta: isize) -> Block {
+        let mut block = self.clone();
+        for range in &mut block.ranges {
+            if *range.start() > after {
+                *range = range.start().saturating_add_signed(delta)
+                    ..=range.end().saturating_add_signed(delta);
+            }
+        }
+        if let Some(position) = block.position.filter(|&position| position > after) {
+            block.position = Some(position.saturating_add_signed(delta));
+            block.html = shift_lines(&block.html, delta);
+        }
+        block
+    }
+}
+
+/// What a block contributes to the state the other blocks read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct BlockState {
+    /// Heading levels and text, which the slugs of later headings and the
+    /// table of contents depend on.
+    headings: Vec<(u8, String)>,
+    slugs: Vec<String>,
+    /// `[ ]`/`[x]` tasks, which the indices of later tasks depend on.
+    tasks: usize,
+    /// Footnote references with their footnote number and reference count.
+    footnote_references: Vec<(String, u32, u32)>,
+}
+
+impl BlockState {
+    /// Concatenate the state of consecutive `blocks`.
+    fn of(blocks: &[Block]) -> BlockState {
+        let mut state = BlockState::default();
+        for block in blocks {
+            state.headings.extend(block.state.headings.iter().cloned());
+            state.slugs.extend(block.state.slugs.iter().cloned());
+            state.tasks += block.state.tasks;
+            state
+                .footnote_references
+                .extend(block.state.footnote_references.iter().cloned());
+        }
+        state
+    }
+}
+
+/// The tasks and footnote references of a document with their source lines,
+/// taken before the transforms replace them with raw HTML.
+#[derive(Debug, Default)]
+struct Marks {
+    tasks: Vec<usize>,
+    footnote_references: Vec<(usize, (String, u32, u32))>,
+}
+
+impl Marks {
+    fn collect<'a>(root: &'a AstNode<'a>) -> Marks {
+        let mut marks = Marks::default();
+        for node in root.descendants() {
+            let ast = node.data.borrow();
+            let line = ast.sourcepos.start.line;
+            match ast.value {
+                NodeValue::TaskItem(..) if tasks::is_checkbox_task(node) => marks.tasks.push(line),
+                NodeValue::FootnoteReference(ref reference) => {
+                    let reference = (reference.name.to_string(), reference.ix, reference.ref_num);
+                    marks.footnote_references.push((line, reference));
+                }
+                _ => {}
+            }
+        }
+        marks
+    }
+
+    /// The state of the block covering `ranges`, with its `headings`.
+    fn state(&self, ranges: &[RangeInclusive<usize>], headings: &[Heading]) -> BlockState {
+        let covers = |line: &usize| ranges.iter().any(|range| range.contains(line));
+        let headings = headings
+            .iter()
+            .filter(|h| covers(&h.line))
+            .collect::<Vec<_>>();
+
+        BlockState {
+            headings: headings.iter().map(|h| (h.level, h.text.clone())).collect(),
+            slugs: headings.iter().map(|h| h.slug.clone()).collect(),
+            tasks: self.tasks.iter().filter(|line| covers(line)).count(),
+            footnote_references: self
+                .footnote_references
+                .iter()
+                .filter(|(line, _)| covers(line))
+                .map(|(_, reference)| reference.clone())
+                .collect(),
+        }
+    }
+}
+
+/// The source of the previous update and the state its blocks share.
+#[derive(Debug, Default)]
+struct Document {
+    lines: Vec<String>,
+    definitions: Vec<DefinitionRun>,
+    /// Hash of the link reference definitions, part of every block key.
+    context: u64,
+    toc: Vec<TocEntry>,
+    /// The last line of the front matter, 0 without one.
+    front_matter_end: usize,
+}
+
+/// A change to the previously rendered block list.
+///
+/// Patches are meant to be applied in order. The `html` of inserted and
+/// replacing blocks is already wrapped in its `data-block-key` element.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub(crate) enum Patch {
+    /// Insert a block before the block keyed `before`, or at the end.
+    Insert {
+        key: String,
+        before: Option<String>,
+        html: String,
+    },
+    /// Replace the block keyed `old_key`.
+    Replace {
+        old_key: String,
+        key: String,
+        html: String,
+    },
+    /// Remove the block keyed `key`.
+    Remove { key: String },
+}
+
+/// Stateful renderer that only re-parses and renders the top-level blocks an
+/// edit changed.
+#[wasm_bindgen]
+pub struct IncrementalRenderer {
+    theme: &'static str,
+    options: RenderOptions,
+    blocks: Vec<Block>,
+    document: Document,
+}
+
+#[wasm_bindgen]
+impl IncrementalRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(theme: Themes, options: &RenderOptions) -> Self {
+        Self {
+            theme: theme.to_str(),
+            options: options.clone(),
+            blocks: Vec::new(),
+            document: Document::default(),
+        }
+    }
+
+    /// Render `markdown` and return the patches that turn the previous block
+    /// list into the new one, as `{ op: "insert" | "replace" | "remove", ... }`
+    /// objects.
+    pub fn update(&mut self, markdown: &str) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.update_blocks(markdown)).unwrap_or(JsValue::UNDEFINED)
+    }
+
+    /// The HTML of all current blocks, each in its `data-block-key` wrapper.
+    pub fn html(&self) -> String {
+        self.blocks.iter().map(Block::wrapped_html).collect()
+    }
+
+    /// Forget the previous render, so the next update inserts every block.
+    pub fn reset(&mut self) {
+        self.blocks.clear();
+        self.document = Document::default();
+    }
+}
+
+impl IncrementalRenderer {
+    pub(crate) fn update_blocks(&mut self, markdown: &str) -> Vec<Patch> {
+        let blocks = match self.render_edit(markdown) {
+            Some(blocks) => blocks,
+            None => self.render_all(markdown),
+        };
+
+        let patches = diff(&self.blocks, &blocks);
+        self.blocks = blocks;
+        patches
+    }
+
+    /// Parse and render the whole document.
+    fn render_all(&mut self, markdown: &str) -> Vec<Block> {
+        let comrak_options = render::comrak_options_for(markdown, &self.options);
+        let arena = Arena::new();
+        let root = render::parse(&arena, markdown, &comrak_options, &self.options);
+
+        let lines = markdown.lines().collect::<Vec<&str>>();
+        let definitions = links::definition_runs(root, &lines);
+        let context = definitions_hash(&definitions);
+        let marks = Marks::collect(root);
+        let metadata = render::prepare(&arena, root, &self.options);
+
+        let mut front_matter_end = 0;
+        let mut blocks = Vec::new();
+        let mut nodes = Vec::new();
+        let blank = |line: usize| {
+            let line = line.checked_sub(1).and_then(|line| lines.get(line));
+            line.is_none_or(|line| line.trim().is_empty())
+        };
+        for (node, ranges) in top_level_blocks(&arena, root) {
+            let ranges = ranges
+                .into_iter()
+                .map(|range| trim_blank_end(range, blank))
+                .collect::<Vec<_>>();
+            if matches!(node.data.borrow().value, NodeValue::FrontMatter(..)) {
+                front_matter_end = *ranges[0].end();
+                continue;
+            }
+
+            let footnotes = matches!(node.data.borrow().value, NodeValue::Document);
+            let state = marks.state(&ranges, &metadata.headings);
+            blocks.push(self.new_block(node, ranges, footnotes, state, &lines, context));
+            nodes.push(Some(node));
+        }
+        self.render_blocks(&mut blocks, &nodes, &comrak_options);
+
+        self.document = Document {
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+            definitions,
+            context,
+            toc: metadata.toc,
+            front_matter_end,
+        };
+        blocks
+    }
+
+    /// Re-parse and render only the blocks around the lines `markdown` changed
+    /// since the last update, or `None` if the edit needs the whole document.
+    ///
+    /// The changed blocks are parsed with the block before and after them,
+    /// which must come out as before for the rest of the document to stand.
+    fn render_edit(&mut self, markdown: &str) -> Option<Vec<Block>> {
+        let old = &self.document;
+        if self.blocks.is_empty() {
+            return None;
+        }
+
+        let lines = markdown.lines().collect::<Vec<&str>>();
+        let prefix = old
+            .lines
+            .iter()
+            .zip(&lines)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let suffix = old.lines[prefix..]
+            .iter()
+            .rev()
+            .zip(lines[prefix..].iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count();
+        if prefix == old.lines.len() && prefix == lines.len() {
+            return Some(self.blocks.clone());
+        }
+        // Front matter only opens the document and is parsed with all of it.
+        let opens_front_matter = self.options.front_matter
+            && lines.first().is_some_and(|line| {
+                let line = line.trim_end();
+                line == front_matter::YAML_DELIMITER || line == front_matter::TOML_DELIMITER
+            });
+        if prefix == 0
+            || prefix < old.front_matter_end
+            || opens_front_matter && old.front_matter_end == 0
+        {
+            return None;
+        }
+
+        let delta = lines.len() as isize - old.lines.len() as isize;
+        // The first unchanged line after the edit, in the old document.
+        let changed_end = old.lines.len() - suffix + 1;
+
+        let footnotes = self.blocks.last().filter(|block| block.footnotes);
+        let content = &self.blocks[..self.blocks.len() - footnotes.is_some() as usize];
+        let first = content
+            .iter()
+            .position(|block| *block.ranges[0].end() >= prefix)
+            .unwrap_or(content.len());
+        let end = content
+            .iter()
+            .position(|block| *block.ranges[0].start() > changed_end)
+            .unwrap_or(content.len())
+            .max(first);
+        let previous = first.checked_sub(1);
+        let next = (end < content.len()).then_some(end);
+
+        let window_start =
+            previous.map_or(old.front_matter_end + 1, |i| *content[i].ranges[0].start());
+        // Without a block after the edit, the window ends at the footnote
+        // definitions after it, or with the document.
+        let last_line = content
+            .last()
+            .map_or(prefix, |block| *block.ranges[0].end());
+        let window_end = match next {
+            Some(i) => *content[i].ranges[0].end(),
+            None => footnotes
+                .iter()
+                .flat_map(|block| &block.ranges)
+                .map(|range| *range.start())
+                .filter(|&start| start > last_line.max(old.lines.len() - suffix))
+                .min()
+                .map_or(old.lines.len(), |start| start - 1),
+        };
+        let new_window_end = window_end.checked_add_signed(delta)?;
+        if new_window_end + 1 < window_start {
+            return None;
+        }
+
+        if let Some(footnotes) = footnotes {
+            let in_window = footnotes
+                .ranges
+                .iter()
+                .any(|range| *range.end() >= window_start && *range.start() <= window_end);
+            let moved = delta != 0
+                && footnotes
+                    .ranges
+                    .iter()
+                    .any(|range| *range.start() > window_end);
+            if in_window || footnotes.position.is_some() && moved {
+                return None;
+            }
+        }
+
+        // The window, between the definitions before it and those after it
+        // and the footnote definitions, so every reference resolves as in the
+        // whole document.
+        let mut text = String::new();
+        for run in old
+            .definitions
+            .iter()
+            .filter(|run| *run.lines.end() < window_start)
+        {
+            text.push_str(run.text.trim_start());
+            text.push_str("\n\n");
+        }
+        if text.is_empty() {
+            // Keeps a `---` on the first line from reading as front matter.
+            text.push('\n');
+        }
+        let offset = text.matches('\n').count();
+        for line in &lines[window_start - 1..new_window_end] {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push('\n');
+        for run in old
+            .definitions
+            .iter()
+            .filter(|run| *run.lines.start() > window_end)
+        {
+            text.push_str(run.text.trim_start());
+            text.push_str("\n\n");
+        }
+        for range in footnotes.iter().flat_map(|block| &block.ranges) {
+            for line in &old.lines[range.start() - 1..*range.end()] {
+                text.push_str(line);
+                text.push('\n');
+            }
+            text.push('\n');
+        }
+
+        let comrak_options = render::comrak_options_for(markdown, &self.options);
+        let arena = Arena::new();
+        let root = render::parse(&arena, &text, &comrak_options, &self.options);
+        let shift = window_start as isize - 1 - offset as isize;
+        let window = window_start..=new_window_end;
+
+        // The link reference definitions must be the same as before.
+        let text_lines = text.lines().collect::<Vec<&str>>();
+        let definitions = links::definition_runs(root, &text_lines)
+            .into_iter()
+            .map(|run| run.shifted(shift))
+            .filter(|run| window.contains(run.lines.start()))
+            .collect::<Vec<_>>();
+        let old_definitions = old
+            .definitions
+            .iter()
+            .filter(|run| (window_start..=window_end).contains(run.lines.start()))
+            .map(|run| &run.text);
+        if !definitions.iter().map(|run| &run.text).eq(old_definitions) {
+            return None;
+        }
+
+        shift_sourcepos(root, shift);
+        let blank = |line: usize| {
+            let line = line
+                .checked_add_signed(-shift)
+                .and_then(|line| line.checked_sub(1));
+            line.and_then(|line| text_lines.get(line))
+                .is_none_or(|line| line.trim().is_empty())
+        };
+        let mut blocks = Vec::new();
+        for node in root.children() {
+            let ast = node.data.borrow();
+            let range = trim_blank_end(ast.sourcepos.start.line..=ast.sourcepos.end.line, blank);
+            let in_window = window.contains(range.start());
+            match ast.value {
+                NodeValue::FootnoteDefinition(..) if !in_window => {}
+                _ if in_window && window.contains(range.end()) => blocks.push((node, range)),
+                _ => return None,
+            }
+        }
+        // The blocks around the edit must parse as they did.
+        let first_range = blocks.first().map(|(_, range)| range.clone());
+        let last_range = blocks.last().map(|(_, range)| range.clone());
+        if let Some(previous) = previous
+            && first_range.as_ref() != Some(&content[previous].ranges[0])
+        {
+            return None;
+        }
+        if let Some(next) = next {
+            let range = &content[next].ranges[0];
+            let moved =
+                range.start().checked_add_signed(delta)?..=range.end().checked_add_signed(delta)?;
+            if last_range != Some(moved) {
+                return None;
+            }
+        }
+        let around = previous.is_some() as usize + next.is_some() as usize;
+        if blocks.len() < around {
+            return None;
+        }
+        let middle = &blocks[previous.is_some() as usize..blocks.len() - next.is_some() as usize];
+
+        // The edit must leave what other blocks read from it as it was.
+        let metadata = render::collect_metadata(root);
+        let middle_ranges = middle
+            .iter()
+            .map(|(_, range)| range.clone())
+            .collect::<Vec<_>>();
+        let state = Marks::collect(root).state(&middle_ranges, &metadata.headings);
+        let old_state = BlockState::of(&content[first..end]);
+        let uses_headings = self.options.heading_ids || self.options.toc_placeholder;
+        let numbers_tasks = tasks::renders_tasks(&self.options) && self.options.interactive_tasks;
+        if uses_headings && state.headings != old_state.headings
+            || numbers_tasks && state.tasks != old_state.tasks
+            || !state
+                .footnote_references
+                .iter()
+                .map(|(name, ..)| name)
+                .eq(old_state.footnote_references.iter().map(|(name, ..)| name))
+        {
+            return None;
+        }
+
+        // Footnote numbers and reference counts are the whole document's.
+        let mut numbers = old_state.footnote_references.iter();
+        for node in root.descendants() {
+            let line = node.data.borrow().sourcepos.start.line;
+            if let NodeValue::FootnoteReference(ref mut reference) = node.data.borrow_mut().value
+                && middle_ranges.iter().any(|range| range.contains(&line))
+                && let Some((_, ix, ref_num)) = numbers.next()
+            {
+                reference.ix = *ix;
+                reference.ref_num = *ref_num;
+            }
+        }
+        let marks = Marks::collect(root);
+
+        let first_task = BlockState::of(&content[..first - previous.is_some() as usize]).tasks;
+        let mut result = metadata;
+        result.toc = old.toc.clone();
+        render::transform(&arena, root, &self.options, &mut result, first_task);
+
+        let mut slugs = old_state.slugs.into_iter();
+        let mut new_blocks = content[..first].to_vec();
+        let mut nodes = vec![None; first];
+        for (node, range) in middle {
+            let mut state = marks.state(std::slice::from_ref(range), &result.headings);
+            if uses_headings {
+                state.slugs = slugs.by_ref().take(state.headings.len()).collect();
+            }
+            new_blocks.push(self.new_block(
+                node,
+                vec![range.clone()],
+                false,
+                state,
+                &lines,
+                old.context,
+            ));
+            nodes.push(Some(*node));
+        }
+        for block in content[end..].iter().chain(footnotes) {
+            new_blocks.push(block.shifted(window_end, delta));
+            nodes.push(None);
+        }
+        self.render_blocks(&mut new_blocks, &nodes, &comrak_options);
+
+        let mut definitions = old
+            .definitions
+            .iter()
+            .filter(|run| *run.lines.end() < window_start)
+            .cloned()
+            .chain(definitions)
+            .collect::<Vec<_>>();
+        definitions.extend(
+            old.definitions
+                .iter()
+                .filter(|run| *run.lines.start() > window_end)
+                .map(|run| run.shifted(delta)),
+        );
+        self.document.lines = lines.iter().map(|line| line.to_string()).collect();
+        self.document.definitions = definitions;
+        Some(new_blocks)
+    }
+
+    fn new_block<'a>(
+        &self,
+        node: &'a AstNode<'a>,
+        ranges: Vec<RangeInclusive<usize>>,
+        footnotes: bool,
+        state: BlockState,
+        lines: &[&str],
+        context: u64,
+    ) -> Block {
+        let mut hasher = DefaultHasher::new();
+        context.hash(&mut hasher);
+        for range in &ranges {
+            source_lines(lines, range).hash(&mut hasher);
+        }
+        hash_document_state(node, &mut hasher);
+        self.options
+            .heading_ids
+            .then_some(&state.slugs)
+            .hash(&mut hasher);
+
+        let positional =
+            self.options.sourcepos || self.options.interactive_tasks && state.tasks > 0;
+        Block {
+            key: String::new(),
+            html: String::new(),
+            position: positional.then(|| *ranges[0].start()),
+            ranges,
+            footnotes,
+            hash: hasher.finish(),
+            state,
+        }
+    }
+
+    /// Key `blocks` in order, and render those with a node unless the previous
+    /// update has HTML for their key.
+    fn render_blocks<'a>(
+        &self,
+        blocks: &mut [Block],
+        nodes: &[Option<&'a AstNode<'a>>],
+        comrak_options: &Options,
+    ) {
+        let previous = self
+            .blocks
+            .iter()
+            .map(|block| (block.key.as_str(), block.html.as_str()))
+            .collect::<HashMap<&str, &str>>();
+        let mut occurrences: HashMap<u64, usize> = HashMap::new();
+
+        for (block, node) in blocks.iter_mut().zip(nodes) {
+            let mut hasher = DefaultHasher::new();
+            (block.hash, block.position).hash(&mut hasher);
+            let hash = hasher.finish();
+            let occurrence = occurrences.entry(hash).or_default();
+            block.key = format!("{:016x}-{}", hash, occurrence);
+            *occurrence += 1;
+
+            let Some(node) = node else {
+                continue;
+            };
+            block.html = match previous.get(block.key.as_str()) {
+                Some(html) => html.to_string(),
+                None => {
+                    let slugs = self.options.heading_ids.then(|| block.state.slugs.clone());
+                    render::format_node(node, comrak_options, &PLUGINS[self.theme], slugs)
+                }
+            };
+        }
+    }
+}
+
+/// The top-level blocks of `root` with the source line ranges they cover.
+///
+/// comrak moves footnote definitions to the end of the document, and they
+/// render into a single footnotes section, so they are grouped into one block
+/// under a document node.
+fn top_level_blocks<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+) -> Vec<(&'a AstNode<'a>, Vec<RangeInclusive<usize>>)> {
+    let mut blocks = Vec::new();
+    let mut footnotes: Option<(&'a AstNode<'a>, Vec<RangeInclusive<usize>>)> = None;
+
+    for child in root.children().collect::<Vec<_>>() {
+        let sourcepos = child.data.borrow().sourcepos;
+        let range = sourcepos.start.line..=sourcepos.end.line;
+
+        if matches!(child.data.borrow().value, NodeValue::FootnoteDefinition(..)) {
+            let (group, ranges) = footnotes
+                .get_or_insert_with(|| (arena.alloc(NodeValue::Document.into()), Vec::new()));
+            group.append(child);
+            ranges.push(range);
+        } else {
+            blocks.push((child, vec![range]));
+        }
+    }
+
+    blocks.extend(footnotes);
+    blocks
+}
+
+/// `range` without the blank lines at its end, which comrak counts into some
+/// blocks depending on what follows them.
+fn trim_blank_end(
+    range: RangeInclusive<usize>,
+    blank: impl Fn(usize) -> bool,
+) -> RangeInclusive<usize> {
+    let mut end = *range.end();
+    while end > *range.start() && blank(end) {
+        end -= 1;
+    }
+    *range.start()..=end
+}
+
+/// Hash the link reference definitions, wherever they are. A change there can
+/// affect any block, so it is part of every key.
+fn definitions_hash(definitions: &[DefinitionRun]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for run in definitions {
+        run.text.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Hash what the HTML of `block` takes from the rest of the document rather
+/// than from its own source: the raw markup written by the AST transforms,
+/// which carries footnote reference numbers and previews of the referenced
+/// footnotes' text, and the number of references to each footnote definition,
+/// which sets its back-references. Source lines are left out, as the key
+/// takes the block's position separately.
+fn hash_document_state<'a>(block: &'a AstNode<'a>, hasher: &mut DefaultHasher) {
+    for node in block.descendants() {
+        match node.data.borrow().value {
+            NodeValue::Raw(ref raw) => LINE_ATTR_REGEX.replace_all(raw, "").hash(hasher),
+            NodeValue::FootnoteReference(ref reference) => {
+                (reference.ix, reference.ref_num).hash(hasher);
+            }
+            NodeValue::FootnoteDefinition(ref definition) => {
+                definition.total_references.hash(hasher);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Move the source lines of `root` and its descendants, and those written
+/// into their raw HTML, by `delta`.
+fn shift_sourcepos<'a>(root: &'a AstNode<'a>, delta: isize) {
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        ast.sourcepos.start.line = ast.sourcepos.start.line.saturating_add_signed(delta);
+        ast.sourcepos.end.line = ast.sourcepos.end.line.saturating_add_signed(delta);
+        if let NodeValue::Raw(ref mut raw) = ast.value {
+            *raw = shift_lines(raw, delta);
+        }
+    }
+}
+
+/// `html` with the source lines in its attributes moved by `delta`.
+fn shift_lines(html: &str, delta: isize) -> String {
+    let shift = |line: &str| {
+        line.parse::<usize>()
+            .map_or(0, |line| line.saturating_add_signed(delta))
+    };
+
+    LINE_ATTR_REGEX
+        .replace_all(html, |captures: &Captures| match captures.get(1) {
+            Some(_) => format!(
+                "{}{}{}{}{}",
+                &captures[1],
+                shift(&captures[2]),
+                &captures[3],
+                shift(&captures[4]),
+                &captures[5]
+            ),
+            None => format!("{}{}{}", &captures[6], shift(&captures[7]), &captures[8]),
+        })
+        .into_owned()
+}
+
+fn source_lines<'l, 's>(lines: &'l [&'s str], range: &RangeInclusive<usize>) -> &'l [&'s str] {
+    let start = range.start().saturating_sub(1);
+    lines.get(start..*range.end()).unwrap_or_default()
+}
+
+/// Patches turning `old` into `new`. Blocks with unchanged keys at either end
+/// are kept; the changed middle is replaced pairwise, with the surplus removed
+/// or inserted.
+fn diff(old: &[Block], new: &[Block]) -> Vec<Patch> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(a, b)| a.key == b.key)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.key == b.key)
+        .count();
+
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+    let mut patches = Vec::new();
+
+    for (old_block, new_block) in old_changed.iter().zip(new_changed) {
+        patches.push(Patch::Replace {
+            old_key: old_block.key.clone(),
+            key: new_block.key.clone(),
+            html: new_block.wrapped_html(),
+        });
+    }
+
+    for old_block in old_changed.iter().skip(new_changed.len()) {
+        patches.push(Patch::Remove {
+            key: old_block.key.clone(),
+        });
+    }
+
+    let before = new.get(new.len() - suffix).map(|block| block.key.clone());
+    for new_block in new_changed.iter().skip(old_changed.len()) {
+        patches.push(Patch::Insert {
+            key: new_block.key.clone(),
+            before: before.clone(),
+            html: new_block.wrapped_html(),
+        });
+    }
+
+    patches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(patches: &[Patch]) -> Vec<&str> {
+        patches
+            .iter()
+            .map(|patch| match patch {
+                Patch::Insert { .. } => "insert",
+                Patch::Replace { .. } => "replace",
+                Patch::Remove { .. } => "remove",
+            })
+            .collect()
+    }
+
+    fn block_keys(renderer: &IncrementalRenderer) -> Vec<&str> {
+        renderer
+            .blocks
+            .iter()
+            .map(|block| block.key.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_first_update_inserts_every_block() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        let patches = renderer.update_blocks("# Title\n\nText\n\n```rust\nfn main() {}\n```\n");
+
+        assert_eq!(keys(&patches), vec!["insert", "insert", "insert"]);
+        assert!(
+            patches
+                .iter()
+                .all(|patch| matches!(patch, Patch::Insert { before: None, .. }))
+        );
+        assert!(renderer.html().contains("<h1>Title</h1>"));
+        assert!(renderer.html().contains("code-block-wrapper"));
+    }
+
+    #[test]
+    fn test_edit_replaces_only_changed_block() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("# Title\n\nFirst\n\nSecond\n");
+        let before = renderer.blocks.clone();
+
+        let patches = renderer.update_blocks("# Title\n\nFirst edited\n\nSecond\n");
+        match patches.as_slice() {
+            [Patch::Replace { old_key, html, .. }] => {
+                assert_eq!(old_key, &before[1].key);
+                assert!(html.contains("First edited"));
+            }
+            _ => panic!("expected a single replace: {:?}", patches),
+        }
+        assert_eq!(renderer.blocks[0].key, before[0].key);
+        assert_eq!(renderer.blocks[2].key, before[2].key);
+    }
+
+    #[test]
+    fn test_insert_and_remove_blocks() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("A\n\nC\n");
+        let c_key = renderer.blocks[1].key.clone();
+
+        let patches = renderer.update_blocks("A\n\nB\n\nC\n");
+        match patches.as_slice() {
+            [Patch::Insert { before, html, .. }] => {
+                assert_eq!(before.as_ref(), Some(&c_key));
+                assert!(html.contains("<p>B</p>"));
+            }
+            _ => panic!("expected a single insert: {:?}", patches),
+        }
+
+        let patches = renderer.update_blocks("A\n");
+        assert_eq!(keys(&patches), vec!["remove", "remove"]);
+    }
+
+    #[test]
+    fn test_duplicate_blocks_get_distinct_keys() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("Same\n\n***\n\nSame\n");
+
+        assert_eq!(renderer.blocks.len(), 3);
+        assert_ne!(renderer.blocks[0].key, renderer.blocks[2].key);
+    }
+
+    #[test]
+    fn test_reference_definition_change_rerenders_blocks() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("[link]\n\n[link]: https://a.example\n");
+
+        let patches = renderer.update_blocks("[link]\n\n[link]: https://b.example\n");
+        match patches.as_slice() {
+            [Patch::Replace { html, .. }] => assert!(html.contains("https://b.example")),
+            _ => panic!("expected a single replace: {:?}", patches),
+        }
+    }
+
+    #[test]
+    fn test_shifted_blocks_rerender_sourcepos() {
+        let mut options = RenderOptions::new();
+        options.sourcepos = true;
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &options);
+        renderer.update_blocks("First\n");
+
+        let patches = renderer.update_blocks("New\n\nFirst\n");
+        assert_eq!(keys(&patches), vec!["replace", "insert"]);
+        assert!(
+            renderer
+                .html()
+                .contains("<p data-sourcepos=\"3:1-3:5\">First</p>")
+        );
+    }
+
+    #[test]
+    fn test_footnote_numbers_rerender_references() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("B[^b]\n\n[^a]: A.\n[^b]: B.\n");
+        assert!(renderer.blocks[0].html.contains(">1</a>"));
+
+        renderer.update_blocks("A[^a]\n\nB[^b]\n\n[^a]: A.\n[^b]: B.\n");
+        assert!(renderer.blocks[1].html.contains(">2</a>"));
+    }
+
+    #[test]
+    fn test_task_indices_rerender_after_new_task() {
+        let mut options = RenderOptions::new();
+        options.interactive_tasks = true;
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &options);
+        renderer.update_blocks("Intro\n\n- [ ] later\n");
+
+        renderer.update_blocks("- [ ] new\n\nIntro\n\n- [ ] later\n");
+        assert!(renderer.blocks[2].html.contains("data-task-index=\"1\""));
+    }
+
+    #[test]
+    fn test_footnote_edit_rerenders_references() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("Claim[^1]\n\n[^1]: Old source.\n");
+
+        let patches = renderer.update_blocks("Claim[^1]\n\n[^1]: New source.\n");
+        assert_eq!(keys(&patches), vec!["replace", "replace"]);
+        assert!(
+            renderer.blocks[0]
+                .html
+                .contains("data-footnote-content=\"New source.\"")
+        );
+    }
+
+    #[test]
+    fn test_edits_render_like_the_whole_document() {
+        let document = "# Title\n\nSee [docs][d] and a note[^n].\n\n- [ ] one\n- [x] two\n\n\
+                        ## Title\n\n```rust\nfn main() {}\n```\n\n[d]: /docs\n\n> Quote\n\n\
+                        | a | b |\n|---|---|\n| 1 | 2 |\n\nLast\n\n[^n]: Note.\n";
+        let edits = [
+            document.replace("a note", "one note"),
+            document.replace("```rust\n", "```rust\nlet x = 1;\n"),
+            document.replace("> Quote\n", "> Quote\n\nMore\n"),
+            document.replace("- [x] two", "- [x] two\n- [ ] three"),
+            document.replace("- [x] two", "- [ ] two"),
+            document.replace("## Title", "## Other"),
+            document.replace("[d]: /docs", "[d]: /other"),
+            document.replace("a note[^n]", "a note[^n] again[^n]"),
+            document.replace("\n> Quote\n", "\n[d]: /first\n> Quote\n"),
+            document.replace("| 1 | 2 |\n", "| 1 | 2 |\n| 3 | 4 |\n"),
+            document.replace("Last\n", "Last\n\nAppended\n"),
+            document.replace("> Quote\n\n", ""),
+            document.replace("```rust\n", "```rust\n[d]: /inside\n"),
+            document.replace("```\n\n[d]", "\n[d]"),
+        ];
+
+        for sourcepos in [false, true] {
+            let mut options = RenderOptions::new();
+            options.sourcepos = sourcepos;
+            options.heading_ids = true;
+            options.interactive_tasks = true;
+
+            for edit in &edits {
+                let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &options);
+                renderer.update_blocks(document);
+                renderer.update_blocks(edit);
+                let mut fresh = IncrementalRenderer::new(Themes::OneHalfDark, &options);
+                fresh.update_blocks(edit);
+
+                assert_eq!(renderer.html(), fresh.html(), "edit: {:?}", edit);
+                assert_eq!(
+                    block_keys(&renderer),
+                    block_keys(&fresh),
+                    "edit: {:?}",
+                    edit
+                );
+                // The next edit starts from the incremental state.
+                renderer.update_blocks(document);
+                fresh.update_blocks(document);
+                assert_eq!(renderer.html(), fresh.html(), "undo: {:?}", edit);
+            }
+        }
+    }
+
+    #[test]
+    fn test_local_edit_reparses_only_its_blocks() {
+        let mut options = RenderOptions::new();
+        options.heading_ids = true;
+        let document =
+            "Intro\n\n## Section\n\nFirst[^n]\n\nSecond [docs]\n\n[docs]: /docs\n\n[^n]: Note.\n";
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &options);
+        renderer.update_blocks(document);
+
+        assert!(
+            renderer
+                .render_edit(&document.replace("## Section", "## Renamed"))
+                .is_none()
+        );
+        assert!(
+            renderer
+                .render_edit(&document.replace("/docs", "/other"))
+                .is_none()
+        );
+        assert!(
+            renderer
+                .render_edit(&document.replace("First[^n]", "First"))
+                .is_none()
+        );
+        assert!(
+            renderer
+                .render_edit(&document.replace("Second", "Second edited"))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_definition_opening_paragraph_rerenders_blocks() {
+        let mut renderer = IncrementalRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+        renderer.update_blocks("[a]: https://a.example\nText\n\nSee [a].\n");
+
+        let patches = renderer.update_blocks("[a]: https://b.example\nText\n\nSee [a].\n");
+        assert_eq!(keys(&patches), vec!["replace", "replace"]);
+        assert!(renderer.blocks[1].html.contains("https://b.example"));
+    }
+}
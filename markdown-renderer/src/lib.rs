@@ -1,11 +1,43 @@
-use comrak::{Options, markdown_to_html_with_plugins, options::Plugins};
+use comrak::{Arena, Options, options::Plugins, parse_document};
 use std::{collections::HashMap, sync::LazyLock};
 use syntect::{dumps::from_binary, highlighting::ThemeSet, parsing::SyntaxSet};
 use wasm_bindgen::prelude::*;
 
 use crate::syntect_plugin::{SyntectAdapterCached, SyntectAdapterCachedBuilder};
 
+pub use crate::excerpt::ExcerptLimit;
+pub use crate::incremental::IncrementalRenderer;
+pub use crate::links::{Link, LinkKind};
+pub use crate::mentions::IssueReference;
+pub use crate::options::RenderOptions;
+pub use crate::render::{Diagnostic, Heading, RenderResult};
+pub use crate::stats::{DocumentStats, LanguageCount, TaskStateCount};
+pub use crate::streaming::{StreamRenderer, StreamUpdate};
+
+mod alerts;
+mod ast;
+mod containers;
+mod csv;
+mod emoji;
+mod excerpt;
+mod footnotes;
+mod front_matter;
+mod incremental;
+mod links;
+mod mdast;
+mod mentions;
+mod options;
+mod plain_text;
+mod render;
+mod sourcepos;
+mod stats;
+mod streaming;
 mod syntect_plugin;
+mod tables;
+mod tasks;
+mod toc;
+mod urls;
+mod wikilinks;
 
 #[wasm_bindgen]
 pub enum Themes {
@@ -106,41 +138,7 @@ pub static SYNTAX_SET: LazyLock<SyntaxSet> =
 pub static THEME_SET: LazyLock<ThemeSet> =
     LazyLock::new(|| from_binary(include_bytes!("../sublime/themes/all.themedump")));
 
-#[cfg(feature = "sanitize")]
-static OPTIONS: LazyLock<Options> = LazyLock::new(|| {
-    let mut options = Options::default();
-    options.extension.table = true;
-    options.extension.tasklist = true;
-    options.extension.alerts = true;
-    options.extension.underline = true;
-    options.extension.strikethrough = true;
-    options.extension.spoiler = true;
-    options.extension.superscript = true;
-    options.extension.math_code = true;
-    options.extension.math_dollars = true;
-    options.extension.front_matter_delimiter = Some("---".into());
-    options.render.r#unsafe = true;
-
-    options
-});
-
-#[cfg(not(feature = "sanitize"))]
-static OPTIONS: LazyLock<Options> = LazyLock::new(|| {
-    let mut options = Options::default();
-    options.extension.table = true;
-    options.extension.tasklist = true;
-    options.extension.alerts = true;
-    options.extension.underline = true;
-    options.extension.strikethrough = true;
-    options.extension.spoiler = true;
-    options.extension.superscript = true;
-    options.extension.math_code = true;
-    options.extension.math_dollars = true;
-    options.extension.front_matter_delimiter = Some("---".into());
-    options.render.r#unsafe = true;
-
-    options
-});
+static OPTIONS: LazyLock<Options> = LazyLock::new(|| RenderOptions::default().to_comrak());
 
 static ADAPTERS: LazyLock<HashMap<&'static str, SyntectAdapterCached>> = LazyLock::new(|| {
     let mut map = HashMap::with_capacity(THEMES.len());
@@ -237,6 +235,7 @@ static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
         "ruby",
         "s",
         "samp",
+        "section",
         "small",
         "span",
         "strike",
@@ -421,10 +420,16 @@ static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
         tag_attributes.insert(svg_tag, svg_common_attrs.clone());
     }
 
-    // Input attributes (for tasklists)
-    let input_attrs = ["type", "checked", "disabled"]
-        .into_iter()
-        .collect::<HashSet<&str>>();
+    // Input attributes (for tasklists, with the source mapping of interactive ones)
+    let input_attrs = [
+        "type",
+        "checked",
+        "disabled",
+        "data-task-line",
+        "data-task-index",
+    ]
+    .into_iter()
+    .collect::<HashSet<&str>>();
     tag_attributes.insert("input", input_attrs);
 
     // Span attributes (for code block language labels)
@@ -433,26 +438,64 @@ static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
         .collect::<HashSet<&str>>();
     tag_attributes.insert("span", span_attrs);
 
-    // Image attributes
-    let img_attrs = ["src", "alt", "title", "width", "height"]
+    // Image attributes (class for `<img class="emoji">`)
+    let img_attrs = ["src", "alt", "title", "width", "height", "class"]
         .into_iter()
         .collect::<HashSet<&str>>();
 
     tag_attributes.insert("img", img_attrs);
 
-    // Link attributes (note: 'rel' is managed by ammonia automatically)
-    let a_attrs = ["href", "title"].into_iter().collect::<HashSet<&str>>();
+    // Heading ids (for in-page links and the table of contents)
+    let heading_attrs = ["id"].into_iter().collect::<HashSet<&str>>();
+    for heading in ["h1", "h2", "h3", "h4", "h5", "h6"] {
+        tag_attributes.insert(heading, heading_attrs.clone());
+    }
+
+    // Link attributes (note: 'rel' is managed by ammonia automatically), with
+    // the ids and data attributes of footnote references, back-references and
+    // wikilinks
+    let a_attrs = [
+        "href",
+        "title",
+        "id",
+        "aria-label",
+        "data-footnote-ref",
+        "data-footnote-content",
+        "data-footnote-backref",
+        "data-footnote-backref-idx",
+        "data-wikilink",
+    ]
+    .into_iter()
+    .collect::<HashSet<&str>>();
     tag_attributes.insert("a", a_attrs);
 
-    // Table alignment
-    let td_attrs = ["align"].into_iter().collect::<HashSet<&str>>();
-    tag_attributes.insert("td", td_attrs.clone());
-    tag_attributes.insert("th", td_attrs);
+    // Foldable alerts
+    let details_attrs = ["open"].into_iter().collect::<HashSet<&str>>();
+    tag_attributes.insert("details", details_attrs);
+
+    // Footnotes section and the footnote ids its references link to, and the
+    // state of extended task list items
+    let section_attrs = ["data-footnotes"].into_iter().collect::<HashSet<&str>>();
+    tag_attributes.insert("section", section_attrs);
+    let li_attrs = ["id", "data-task-state"]
+        .into_iter()
+        .collect::<HashSet<&str>>();
+    tag_attributes.insert("li", li_attrs);
+
+    // Table alignment and cell spans, and the sort hints of wrapped tables
+    let td_attrs = ["align", "colspan", "rowspan"]
+        .into_iter()
+        .collect::<HashSet<&str>>();
+    tag_attributes.insert("td", td_attrs);
+    let th_attrs = ["align", "colspan", "rowspan", "data-sort-type"]
+        .into_iter()
+        .collect::<HashSet<&str>>();
+    tag_attributes.insert("th", th_attrs);
 
     builder.tag_attributes(tag_attributes);
 
-    // Allow style and class on all elements
-    builder.add_generic_attributes(["style", "class"]);
+    // Allow style, class and source positions (for editor scroll sync) on all elements
+    builder.add_generic_attributes(["style", "class", "data-sourcepos", "data-streaming"]);
 
     // Allow certain URL schemes
     let url_schemes = ["https", "http", "mailto", "data"]
@@ -499,10 +542,9 @@ fn close_code_block_wrappers(html: &str) -> String {
         .into_owned()
 }
 
-#[wasm_bindgen]
-pub fn render_md(markdown: &str, theme: Themes) -> String {
-    let html = markdown_to_html_with_plugins(markdown, &OPTIONS, &PLUGINS[theme.to_str()]);
-    let html = close_code_block_wrappers(&html);
+/// Close code block wrappers and, with the `sanitize` feature, clean the HTML.
+fn finish_html(html: &str) -> String {
+    let html = close_code_block_wrappers(html);
 
     #[cfg(feature = "sanitize")]
     {
@@ -515,10 +557,136 @@ pub fn render_md(markdown: &str, theme: Themes) -> String {
     }
 }
 
+#[wasm_bindgen]
+pub fn render_md(markdown: &str, theme: Themes) -> String {
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown, &OPTIONS);
+
+    footnotes::add_previews(&arena, root);
+    render::format_node(root, &OPTIONS, &PLUGINS[theme.to_str()], None)
+}
+
+/// Render markdown with the given [`RenderOptions`] instead of the defaults.
+#[wasm_bindgen]
+pub fn render_md_with_options(markdown: &str, theme: Themes, options: &RenderOptions) -> String {
+    render::render_document(markdown, options, &PLUGINS[theme.to_str()]).html
+}
+
+/// Render markdown and return the HTML together with the front matter, heading
+/// outline, code languages and warnings found in the document.
+#[wasm_bindgen]
+pub fn render_md_detailed(markdown: &str, theme: Themes, options: &RenderOptions) -> RenderResult {
+    render::render_document(markdown, options, &PLUGINS[theme.to_str()])
+}
+
+/// Find the rendered block element for a 1-based source `line`.
+///
+/// Returns the `data-sourcepos` value of the innermost block element containing
+/// the line when rendered with [`RenderOptions::sourcepos`], so the preview
+/// element can be found with `[data-sourcepos="..."]`.
+#[wasm_bindgen]
+pub fn line_to_block_id(markdown: &str, line: usize, options: &RenderOptions) -> Option<String> {
+    let comrak_options = render::comrak_options_for(markdown, options);
+    let arena = Arena::new();
+    let root = render::parse(&arena, markdown, &comrak_options, options);
+
+    sourcepos::block_at_line(root, line)
+}
+
+/// Parse markdown with the same options as [`render_md`] and return the AST as
+/// mdast-compatible JSON, including unist positions.
+#[wasm_bindgen]
+pub fn parse_md_to_json(markdown: &str) -> String {
+    let options = RenderOptions::default();
+    let comrak_options = render::comrak_options_for(markdown, &options);
+    let arena = Arena::new();
+    let root = render::parse(&arena, markdown, &comrak_options, &options);
+
+    mdast::to_mdast(root).to_string()
+}
+
+/// Render markdown as readable plain text, parsed with the same options as
+/// [`render_md`]. Code is kept verbatim, math as its LaTeX source, links as
+/// their text and tables row by row.
+#[wasm_bindgen]
+pub fn render_plain_text(markdown: &str) -> String {
+    let options = RenderOptions::default();
+    let comrak_options = render::comrak_options_for(markdown, &options);
+    let arena = Arena::new();
+    let root = render::parse(&arena, markdown, &comrak_options, &options);
+
+    plain_text::plain_text(root)
+}
+
+/// Render the start of a document, cut to `limit` on the parsed document so
+/// every tag and code block wrapper is closed. A "…" marks an excerpt that was
+/// cut short.
+#[wasm_bindgen]
+pub fn render_excerpt(markdown: &str, theme: Themes, limit: &ExcerptLimit) -> String {
+    let options = RenderOptions::default();
+    let comrak_options = render::comrak_options_for(markdown, &options);
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown, &comrak_options);
+
+    excerpt::truncate(&arena, root, limit);
+    render::format_node(root, &comrak_options, &PLUGINS[theme.to_str()], None)
+}
+
+/// Count the words, reading time, headings, code blocks, math, images, links
+/// and tasks in a document parsed with the same options as [`render_md`].
+#[wasm_bindgen]
+pub fn document_stats(markdown: &str) -> DocumentStats {
+    document_stats_with_options(markdown, &RenderOptions::default())
+}
+
+/// [`document_stats`] for a document parsed with `options`, counting the
+/// tasks in each of its extended task states.
+#[wasm_bindgen]
+pub fn document_stats_with_options(markdown: &str, options: &RenderOptions) -> DocumentStats {
+    let arena = Arena::new();
+    let root = parse_document(
+        &arena,
+        markdown,
+        &render::comrak_options_for(markdown, options),
+    );
+
+    stats::document_stats(root, options)
+}
+
+/// List every link, autolink, image, raw `<iframe>` source and link reference
+/// definition in a document parsed with the same options as [`render_md`].
+#[wasm_bindgen]
+pub fn extract_links(markdown: &str) -> Vec<Link> {
+    let options = RenderOptions::default();
+    let comrak_options = render::comrak_options_for(markdown, &options);
+    let arena = Arena::new();
+    let root = render::parse(&arena, markdown, &comrak_options, &options);
+
+    links::extract_links(root, markdown)
+}
+
+/// Flip the `[ ]`/`[x]` checkbox of the task at `index`, counted from 0 in
+/// document order as in the `data-task-index` of
+/// [`RenderOptions::interactive_tasks`], and return the updated markdown.
+///
+/// Pass the `options` the document was rendered with, so the tasks are found
+/// the same way. The markdown is returned unchanged if it has no task at
+/// `index`.
+#[wasm_bindgen]
+pub fn toggle_task(markdown: &str, index: usize, options: &RenderOptions) -> String {
+    let comrak_options = render::comrak_options_for(markdown, options);
+    let arena = Arena::new();
+    let root = render::parse(&arena, markdown, &comrak_options, options);
+
+    tasks::toggle_task(root, markdown, index).unwrap_or_else(|| markdown.into())
+}
+
 #[cfg(test)]
 mod test {
     use std::{io::Write, sync::LazyLock};
 
+    use comrak::markdown_to_html_with_plugins;
+
     use super::*;
     use crate::syntect_plugin::{SyntectAdapterUncached, SyntectAdapterUncachedBuilder};
 
@@ -875,4 +1043,1000 @@ mod test {
             normalize_svg_ids(&render_md(md2, Themes::OneHalfDark))
         );
     }
+
+    #[test]
+    fn test_render_options_default_matches_render_md() {
+        let md0 = include_str!("../sample-data/md0.md");
+
+        assert_eq!(
+            normalize_svg_ids(&render_md(md0, Themes::OneHalfDark)),
+            normalize_svg_ids(&render_md_with_options(
+                md0,
+                Themes::OneHalfDark,
+                &RenderOptions::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_render_options_toggles() {
+        let markdown = "Text[^1] and www.example.com\n\n[^1]: A note.\n";
+
+        let mut options = RenderOptions::new();
+        options.footnotes = false;
+        let result = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+        assert!(
+            !result.contains("footnote"),
+            "footnotes should be off when disabled: {}",
+            result
+        );
+
+        let mut options = RenderOptions::new();
+        options.autolink = true;
+        let result = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+        assert!(
+            result.contains("footnotes"),
+            "footnote section should be rendered: {}",
+            result
+        );
+        assert!(
+            result.contains("href=\"http://www.example.com\""),
+            "bare URL should be autolinked: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_render_options_raw_html() {
+        let mut options = RenderOptions::new();
+        options.raw_html = false;
+        let result = render_md_with_options("<b>bold</b>", Themes::OneHalfDark, &options);
+        assert!(
+            !result.contains("<b>"),
+            "raw HTML should be omitted: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_render_options_cached_by_value() {
+        let mut a = RenderOptions::new();
+        a.footnotes = false;
+        let mut b = RenderOptions::new();
+        b.footnotes = false;
+
+        assert!(std::rc::Rc::ptr_eq(
+            &a.comrak_options(),
+            &b.comrak_options()
+        ));
+        assert!(!std::rc::Rc::ptr_eq(
+            &a.comrak_options(),
+            &RenderOptions::new().comrak_options()
+        ));
+
+        // Settings comrak doesn't see share the options.
+        b.heading_ids = true;
+        b.set_task_state('?', Some("question".into()));
+        assert!(std::rc::Rc::ptr_eq(
+            &a.comrak_options(),
+            &b.comrak_options()
+        ));
+    }
+
+    #[test]
+    fn test_render_md_detailed_metadata() {
+        let markdown = concat!(
+            "---\n",
+            "title: Hello\n",
+            "---\n",
+            "\n",
+            "# Intro *here*\n",
+            "\n",
+            "### Skipped\n",
+            "\n",
+            "```rust\n",
+            "fn main() {}\n",
+            "```\n",
+            "\n",
+            "```mermaid\n",
+            "graph TD; A-->B;\n",
+            "```\n",
+            "\n",
+            "```nosuchlang\n",
+            "x\n",
+            "```\n",
+            "\n",
+            "Inline $x^2$ math.\n",
+            "\n",
+            "<iframe src=\"https://example.com\"></iframe>\n",
+        );
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &RenderOptions::new());
+
+        assert_eq!(result.front_matter_raw.as_deref(), Some("title: Hello"));
+        assert_eq!(
+            result.headings,
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Intro here".into(),
+                    slug: "intro-here".into(),
+                    line: 5,
+                },
+                Heading {
+                    level: 3,
+                    text: "Skipped".into(),
+                    slug: "skipped".into(),
+                    line: 7,
+                },
+            ]
+        );
+        assert_eq!(result.languages, vec!["rust", "mermaid", "nosuchlang"]);
+        assert!(result.has_math);
+        assert!(result.has_mermaid);
+        assert!(result.has_iframes);
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|w| w.line)
+                .collect::<Vec<usize>>(),
+            vec![7, 17]
+        );
+        assert!(!result.html.contains("title: Hello"));
+    }
+
+    #[test]
+    fn test_render_md_detailed_toml_front_matter() {
+        let markdown = "+++\ntitle = \"Hello\"\ntags = [\"a\"]\n+++\n\n# Body\n";
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &RenderOptions::new());
+
+        assert_eq!(
+            result.front_matter,
+            Some(serde_json::json!({ "title": "Hello", "tags": ["a"] }))
+        );
+        assert!(!result.html.contains("+++"), "{}", result.html);
+        assert!(!result.html.contains("title"), "{}", result.html);
+        assert_eq!(result.headings[0].line, 6);
+    }
+
+    #[test]
+    fn test_render_md_detailed_invalid_front_matter() {
+        let markdown = "---\ntitle: [unclosed\n---\n\nBody\n";
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &RenderOptions::new());
+
+        assert_eq!(result.front_matter, None);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].message.contains("YAML"));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_heading_ids_and_toc() {
+        let markdown = concat!(
+            "[[toc]]\n",
+            "\n",
+            "# Install\n",
+            "\n",
+            "## Install\n",
+            "\n",
+            "# Usage & Tips <3\n",
+        );
+
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &RenderOptions::new());
+        assert!(!result.html.contains(" id="), "{}", result.html);
+        assert!(result.html.contains("[[toc]]"), "{}", result.html);
+
+        let mut options = RenderOptions::new();
+        options.heading_ids = true;
+        options.toc_placeholder = true;
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &options);
+
+        assert!(
+            result.html.contains("<h1 id=\"install\">Install</h1>"),
+            "{}",
+            result.html
+        );
+        assert!(
+            result.html.contains("<h2 id=\"install-1\">Install</h2>"),
+            "{}",
+            result.html
+        );
+        assert!(
+            result.html.contains("<h1 id=\"usage--tips-3\">"),
+            "{}",
+            result.html
+        );
+        assert!(
+            result.html.contains("<nav class=\"toc\">"),
+            "{}",
+            result.html
+        );
+        assert!(
+            result
+                .html
+                .contains("<a href=\"#usage--tips-3\">Usage &amp; Tips &lt;3</a>"),
+            "{}",
+            result.html
+        );
+        assert!(!result.html.contains("[[toc]]"), "{}", result.html);
+
+        assert_eq!(result.toc.len(), 2);
+        assert_eq!(result.toc[0].children[0].slug, "install-1");
+    }
+
+    #[test]
+    fn test_sourcepos_on_code_block_wrapper() {
+        let markdown = "# Title\n\n```rust\nfn main() {}\n```\n";
+        let mut options = RenderOptions::new();
+        options.sourcepos = true;
+        let result = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        assert!(
+            result.contains("<h1 data-sourcepos=\"1:1-1:7\">"),
+            "{}",
+            result
+        );
+        assert!(
+            result.contains("<div class=\"code-block-wrapper\" data-sourcepos=\"3:1-5:3\">"),
+            "{}",
+            result
+        );
+        assert!(!result.contains("<pre data-sourcepos"), "{}", result);
+        assert_eq!(result.matches("data-sourcepos").count(), 2, "{}", result);
+
+        assert_eq!(
+            line_to_block_id(markdown, 4, &options).as_deref(),
+            Some("3:1-5:3")
+        );
+    }
+
+    #[test]
+    fn test_line_to_block_id_in_containers() {
+        let markdown = "::: tip\nInside\n:::\n";
+        let mut options = RenderOptions::new();
+        options.sourcepos = true;
+        options.containers = true;
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        let container = line_to_block_id(markdown, 1, &options).unwrap();
+        assert_eq!(container, "1:1-3:3");
+        assert!(html.contains(&format!("data-sourcepos=\"{}\"", container)));
+        assert_eq!(
+            line_to_block_id(markdown, 2, &options).as_deref(),
+            Some("2:1-2:6")
+        );
+        assert!(html.contains("<p data-sourcepos=\"2:1-2:6\">Inside</p>"));
+    }
+
+    #[test]
+    fn test_line_to_block_id_after_toml_front_matter() {
+        let markdown = "+++\ntitle = \"T\"\n+++\n\n# Title\n";
+        let mut options = RenderOptions::new();
+        options.sourcepos = true;
+
+        assert_eq!(line_to_block_id(markdown, 2, &options), None);
+        assert_eq!(
+            line_to_block_id(markdown, 5, &options).as_deref(),
+            Some("5:1-5:7")
+        );
+    }
+
+    #[test]
+    fn test_streaming_closes_unfinished_tail() {
+        let mut options = RenderOptions::new();
+        options.streaming = true;
+
+        let result = render_md_with_options("Intro\n\nSome **bold", Themes::OneHalfDark, &options);
+        assert!(result.contains("<p>Intro</p>"), "{}", result);
+        assert!(
+            result.contains("<p data-streaming=\"true\">Some <strong>bold</strong></p>"),
+            "{}",
+            result
+        );
+
+        let result = render_md_with_options(
+            "```rust\nfn main() {\n\nstill code",
+            Themes::OneHalfDark,
+            &options,
+        );
+        assert!(
+            result.contains("<div class=\"code-block-wrapper\" data-streaming=\"true\">"),
+            "{}",
+            result
+        );
+        assert!(
+            result.trim_end().ends_with("</code></pre></div>"),
+            "{}",
+            result
+        );
+        assert_eq!(result.matches("data-streaming").count(), 1, "{}", result);
+    }
+
+    #[test]
+    fn test_excerpt_closes_tags() {
+        let mut limit = ExcerptLimit::new();
+        limit.chars = Some(12);
+
+        let result = render_excerpt(
+            "Hello **bold world** and more text.\n\nSecond paragraph.",
+            Themes::OneHalfDark,
+            &limit,
+        );
+        assert_eq!(result, "<p>Hello <strong>bold</strong>…</p>\n");
+
+        let result = render_excerpt(
+            "```\nline one\nline two\n```\n\nAfter",
+            Themes::OneHalfDark,
+            &limit,
+        );
+        assert!(result.contains("line one"), "{}", result);
+        assert!(!result.contains("line two"), "{}", result);
+        assert!(
+            result.ends_with("</code></pre></div>\n<p>…</p>\n"),
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_excerpt_block_limit_and_media() {
+        let mut limit = ExcerptLimit::new();
+        limit.blocks = Some(2);
+        limit.drop_media = true;
+
+        let markdown = concat!(
+            "![cat](cat.png)\n",
+            "\n",
+            "Look <iframe src=\"https://example.com\"></iframe>here\n",
+            "\n",
+            "- one\n",
+            "- two\n",
+            "\n",
+            "Rest\n",
+        );
+        let result = render_excerpt(markdown, Themes::OneHalfDark, &limit);
+        assert_eq!(
+            result,
+            "<p>Look here</p>\n<ul>\n<li>one</li>\n<li>two…</li>\n</ul>\n"
+        );
+
+        limit.blocks = None;
+        assert_eq!(
+            render_excerpt("Short", Themes::OneHalfDark, &limit),
+            "<p>Short</p>\n"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_base_urls() {
+        let mut options = RenderOptions::new();
+        options.link_base_url = Some("/repo/blob/main/".into());
+        options.image_base_url = Some("https://raw.example.com/main/".into());
+        options.md_link_extension = Some(String::new());
+
+        let result = render_md_with_options(
+            "[Next](./next.md#usage) ![Diagram](img/a.png) [Site](https://example.com)",
+            Themes::OneHalfDark,
+            &options,
+        );
+        assert!(
+            result.contains("<a href=\"/repo/blob/main/next#usage\">Next</a>"),
+            "{}",
+            result
+        );
+        assert!(
+            result
+                .contains("<img src=\"https://raw.example.com/main/img/a.png\" alt=\"Diagram\" />"),
+            "{}",
+            result
+        );
+        assert!(
+            result.contains("<a href=\"https://example.com\">Site</a>"),
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_wikilinks() {
+        let mut options = RenderOptions::new();
+        options.wikilinks = true;
+        options.toc_placeholder = true;
+        options
+            .set_wikilink_pages(r#"{ "Home": "/wiki/home", "Setup Guide": "/wiki/setup" }"#)
+            .unwrap();
+
+        let result = render_md_detailed(
+            "[[toc]]\n\nSee [[Setup Guide|the guide]], [[Home]] and [[Missing <Page>]]. [[Home]]",
+            Themes::OneHalfDark,
+            &options,
+        );
+        assert!(
+            result.html.contains("<nav class=\"toc\">"),
+            "{}",
+            result.html
+        );
+        assert!(
+            result
+                .html
+                .contains("<a href=\"/wiki/setup\" data-wikilink=\"true\">the guide</a>"),
+            "{}",
+            result.html
+        );
+        assert!(
+            result.html.contains("<a class=\"wikilink-missing\" title=\"Missing &lt;Page&gt;\">Missing &lt;Page&gt;</a>"),
+            "{}",
+            result.html
+        );
+        assert_eq!(
+            result.wikilinks,
+            vec!["Setup Guide", "Home", "Missing <Page>"]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_mentions_and_issue_references() {
+        let mut options = RenderOptions::new();
+        options.mention_url_template = Some("https://github.com/{user}".into());
+        options.issue_url_template = Some("https://github.com/acme/app/issues/{number}".into());
+        options.repo_issue_url_template = Some("https://github.com/{repo}/issues/{number}".into());
+
+        let markdown = concat!(
+            "Thanks @alice, fixes #12 and acme/lib#7.\n",
+            "\n",
+            "Not in `@code #1`, $@math$ or [@link](https://example.com).\n",
+            "\n",
+            "```\n",
+            "@fenced #2\n",
+            "```\n",
+        );
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &options);
+
+        assert!(
+            result.html.contains(concat!(
+                "<p>Thanks <a href=\"https://github.com/alice\">@alice</a>, ",
+                "fixes <a href=\"https://github.com/acme/app/issues/12\">#12</a> ",
+                "and <a href=\"https://github.com/acme/lib/issues/7\">acme/lib#7</a>.</p>",
+            )),
+            "{}",
+            result.html
+        );
+        assert_eq!(result.html.matches("<a href").count(), 4, "{}", result.html);
+
+        assert_eq!(result.mentions, vec!["alice"]);
+        assert_eq!(
+            result.issue_references,
+            vec![
+                IssueReference {
+                    repo: None,
+                    number: 12
+                },
+                IssueReference {
+                    repo: Some("acme/lib".into()),
+                    number: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_emoji_shortcodes() {
+        let mut options = RenderOptions::new();
+        options.emoji = true;
+
+        let markdown = "Ship it :tada: :not_an_emoji: `:tada:` $:tada:$\n\n```\n:tada:\n```\n";
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+        assert!(
+            html.contains("<p>Ship it 🎉 :not_an_emoji: <code>:tada:</code>"),
+            "{}",
+            html
+        );
+        assert_eq!(html.matches("🎉").count(), 1, "{}", html);
+
+        options.emoji_image_url_template = Some("https://cdn.example.com/{codepoints}.svg".into());
+        let html = render_md_with_options("Nice :+1:!", Themes::OneHalfDark, &options);
+        assert!(
+            html.contains(concat!(
+                "<p>Nice <img class=\"emoji\" src=\"https://cdn.example.com/1f44d.svg\" ",
+                "alt=\"👍\" title=\":+1:\" />!</p>",
+            )),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_footnotes() {
+        let markdown = concat!(
+            "Claim[^source] and an aside.^[Inline *note*.]\n",
+            "\n",
+            "Again[^source].\n",
+            "\n",
+            "[^source]: The \"source\" text.\n",
+        );
+        let html = render_md(markdown, Themes::OneHalfDark);
+
+        assert!(
+            html.contains(concat!(
+                "<a href=\"#fn-source\" id=\"fnref-source\" data-footnote-ref ",
+                "data-footnote-content=\"The &quot;source&quot; text.\">1</a>",
+            )),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("id=\"fnref-source-2\" data-footnote-ref data-footnote-content=\"The &quot;source&quot; text.\">1</a>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("data-footnote-content=\"Inline note.\">2</a>"),
+            "{}",
+            html
+        );
+        assert!(html.contains("<li id=\"fn-source\">"), "{}", html);
+        assert!(
+            html.contains("<a href=\"#fnref-source-2\" class=\"footnote-backref\""),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_footnote_anchors_survive_sanitizer() {
+        let result = render_md("Claim[^1]\n\n[^1]: Source.\n", Themes::OneHalfDark);
+
+        for expected in [
+            "<section class=\"footnotes\" data-footnotes",
+            "<li id=\"fn-1\">",
+            "id=\"fnref-1\"",
+            "data-footnote-content=\"Source.\"",
+            "href=\"#fnref-1\"",
+            "aria-label=\"Back to reference 1\"",
+        ] {
+            assert!(
+                result.contains(expected),
+                "missing {}: {}",
+                expected,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_containers() {
+        let mut options = RenderOptions::new();
+        options.containers = true;
+
+        let markdown = concat!(
+            "::: tip Read *this*\n",
+            "Some **text**.\n",
+            "\n",
+            "- a list\n",
+            ":::\n",
+            "\n",
+            "::: details Show more\n",
+            "Hidden.\n",
+            ":::\n",
+            "\n",
+            ":::custom\n",
+            "Plain.\n",
+            ":::\n",
+        );
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        assert!(
+            html.contains(concat!(
+                "<div class=\"container container-tip\">\n",
+                "<p class=\"container-title\">Read *this*</p>\n",
+                "<p>Some <strong>text</strong>.</p>\n",
+                "<ul>\n<li>a list</li>\n</ul>\n",
+                "</div>\n",
+            )),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains(concat!(
+                "<details class=\"container container-details\">\n",
+                "<summary>Show more</summary>\n",
+                "<p>Hidden.</p>\n",
+                "</details>\n",
+            )),
+            "{}",
+            html
+        );
+        assert!(html.contains("<div>\n<p>Plain.</p>\n</div>\n"), "{}", html);
+        assert!(!html.contains(":::"), "{}", html);
+
+        options.containers = false;
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+        assert!(html.contains("::: tip"), "{}", html);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_custom_alerts() {
+        let mut options = RenderOptions::new();
+        options.foldable_alerts = true;
+        options.set_alert_type("note", Some("Hinweis".into()), Some("<svg></svg>".into()));
+        options.set_alert_type("example", None, None);
+
+        let markdown = concat!(
+            "> [!NOTE]\n",
+            "> Localized.\n",
+            "\n",
+            "> [!tip]- Click to expand\n",
+            "> Hidden.\n",
+            "\n",
+            "> [!example]+\n",
+            "> Shown.\n",
+            "\n",
+            "> [!unknown]\n",
+            "> Quote.\n",
+        );
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        assert!(
+            html.contains(concat!(
+                "<div class=\"markdown-alert markdown-alert-note\">\n",
+                "<p class=\"markdown-alert-title\"><svg></svg>Hinweis</p>\n",
+                "<p>Localized.</p>\n",
+                "</div>\n",
+            )),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains(concat!(
+                "<details class=\"markdown-alert markdown-alert-tip\">\n",
+                "<summary class=\"markdown-alert-title\">Click to expand</summary>\n",
+                "<p>Hidden.</p>\n",
+                "</details>\n",
+            )),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains(concat!(
+                "<details class=\"markdown-alert markdown-alert-example\" open>\n",
+                "<summary class=\"markdown-alert-title\">Example</summary>\n",
+                "<p>Shown.</p>\n",
+                "</details>\n",
+            )),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<blockquote>\n<p>[!unknown]\nQuote.</p>\n</blockquote>"),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_interactive_tasks() {
+        let markdown = "- [ ] one\n- [x] two\n- plain\n";
+        let html = render_md(markdown, Themes::OneHalfDark);
+        assert!(html.contains("disabled"), "{}", html);
+
+        let mut options = RenderOptions::new();
+        options.interactive_tasks = true;
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+        assert!(
+            html.contains(concat!(
+                "<li><input type=\"checkbox\" data-task-line=\"1\" data-task-index=\"0\" /> one</li>\n",
+                "<li><input type=\"checkbox\" checked=\"\" data-task-line=\"2\" data-task-index=\"1\" /> two</li>\n",
+                "<li>plain</li>\n",
+            )),
+            "{}",
+            html
+        );
+        assert!(!html.contains("disabled"), "{}", html);
+
+        assert_eq!(
+            toggle_task(markdown, 1, &options),
+            "- [ ] one\n- [ ] two\n- plain\n"
+        );
+        assert_eq!(toggle_task(markdown, 2, &options), markdown);
+
+        // Container fences and TOML front matter don't shift the tasks.
+        options.containers = true;
+        let markdown = "+++\ntitle = \"T\"\n+++\n::: tip\n- [ ] inside\n:::\n";
+        assert_eq!(
+            toggle_task(markdown, 0, &options),
+            "+++\ntitle = \"T\"\n+++\n::: tip\n- [x] inside\n:::\n"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_extended_tasks() {
+        let markdown = "- [-] dropped\n- [~] doing\n- [a] plain\n- [x] done\n";
+        let html = render_md(markdown, Themes::OneHalfDark);
+        assert!(!html.contains("data-task-state"));
+
+        let mut options = RenderOptions::new();
+        options.extended_tasks = true;
+        options.set_task_state('a', Some("assigned".into()));
+        options.set_task_state('~', None);
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        assert!(html.contains(concat!(
+            "<li class=\"task-cancelled\" data-task-state=\"cancelled\">",
+            "<input type=\"checkbox\" disabled=\"\" /> dropped</li>"
+        )));
+        assert!(html.contains("<li>[~] doing</li>"));
+        assert!(html.contains("<li class=\"task-assigned\" data-task-state=\"assigned\">"));
+        assert!(html.contains("<input type=\"checkbox\" checked=\"\" disabled=\"\" /> done"));
+
+        let stats = document_stats_with_options(markdown, &options);
+        assert_eq!((stats.tasks_done, stats.tasks_total), (1, 3));
+        assert_eq!(stats.task_states.len(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_table_wrappers() {
+        let markdown = "| Size | Name | Added |\n|-----:|:----|:-:|\n| 1,024 | a | 2024-01-02 |\n| 12 | b | |\n";
+        let mut options = RenderOptions::new();
+        options.table_wrappers = true;
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        assert_eq!(
+            html,
+            concat!(
+                "<div class=\"table-wrapper\">\n<table>\n<thead>\n<tr>\n",
+                "<th class=\"align-right\" data-sort-type=\"number\">Size</th>\n",
+                "<th class=\"align-left\" data-sort-type=\"text\">Name</th>\n",
+                "<th class=\"align-center\" data-sort-type=\"date\">Added</th>\n",
+                "</tr>\n</thead>\n<tbody>\n<tr>\n",
+                "<td class=\"align-right\">1,024</td>\n",
+                "<td class=\"align-left\">a</td>\n",
+                "<td class=\"align-center\">2024-01-02</td>\n",
+                "</tr>\n<tr>\n",
+                "<td class=\"align-right\">12</td>\n",
+                "<td class=\"align-left\">b</td>\n",
+                "<td class=\"align-center\"></td>\n",
+                "</tr>\n</tbody>\n</table>\n</div>\n",
+            )
+        );
+
+        let html = render_md(markdown, Themes::OneHalfDark);
+        assert!(html.contains("<th align=\"right\">Size</th>"));
+        assert!(!html.contains("table-wrapper"));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_table_captions_and_spans() {
+        let markdown = concat!(
+            "| Quarter | Sales ||\n",
+            "| :-- | --- | --- |\n",
+            "| Q1 | 10 | 12 |\n",
+            "| ^^ | 11 | |\n",
+            "\n",
+            "Table: Sales by *quarter*\n",
+        );
+        let mut options = RenderOptions::new();
+        options.table_captions = true;
+        options.table_spans = true;
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        assert_eq!(
+            html,
+            concat!(
+                "<table>\n<caption>Sales by <em>quarter</em></caption>\n",
+                "<thead>\n<tr>\n",
+                "<th align=\"left\">Quarter</th>\n",
+                "<th colspan=\"2\">Sales</th>\n",
+                "</tr>\n</thead>\n<tbody>\n<tr>\n",
+                "<td align=\"left\" rowspan=\"2\">Q1</td>\n",
+                "<td>10</td>\n",
+                "<td>12</td>\n",
+                "</tr>\n<tr>\n",
+                "<td>11</td>\n",
+                "<td></td>\n",
+                "</tr>\n</tbody>\n</table>\n",
+            )
+        );
+
+        let html = render_md(markdown, Themes::OneHalfDark);
+        assert!(html.contains("<p>Table: Sales by <em>quarter</em></p>"));
+        assert!(html.contains("<td>^^</td>"));
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_csv_tables() {
+        let markdown = "```csv delimiter=;\nName;Note\n\"Doe; J\";<b>\n```\n\n```tsv header=false\na\tb\n```\n\n```csv\n\"open,\n```\n";
+        let mut options = RenderOptions::new();
+        options.csv_tables = true;
+        let result = render_md_detailed(markdown, Themes::OneHalfDark, &options);
+
+        assert!(result.html.contains(concat!(
+            "<table>\n<thead>\n<tr>\n<th>Name</th>\n<th>Note</th>\n</tr>\n</thead>\n",
+            "<tbody>\n<tr>\n<td>Doe; J</td>\n<td>&lt;b&gt;</td>\n</tr>\n</tbody>\n</table>\n",
+            "<table>\n<tbody>\n<tr>\n<td>a</td>\n<td>b</td>\n</tr>\n</tbody>\n</table>\n",
+        )));
+        assert!(result.html.contains("code-block-wrapper"));
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].line, 11);
+
+        let html = render_md(markdown, Themes::OneHalfDark);
+        assert!(!html.contains("<table>"));
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_task_states_survive_sanitizer() {
+        let mut options = RenderOptions::new();
+        options.extended_tasks = true;
+        let html = render_md_with_options("- [-] dropped\n", Themes::OneHalfDark, &options);
+
+        for expected in ["class=\"task-cancelled\"", "data-task-state=\"cancelled\""] {
+            assert!(html.contains(expected), "missing {}: {}", expected, html);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_wikilinks_survive_sanitizer() {
+        let mut options = RenderOptions::new();
+        options.wikilinks = true;
+        let html = render_md_with_options("[[Home]]", Themes::OneHalfDark, &options);
+
+        assert!(html.contains("data-wikilink=\"true\""), "{}", html);
+    }
+
+    #[test]
+    fn test_excerpt_drops_unclosed_html_blocks() {
+        let mut limit = ExcerptLimit::new();
+        limit.chars = Some(10);
+
+        let markdown = concat!(
+            "<details>\n",
+            "<summary>More</summary>\n",
+            "\n",
+            "Hidden text that goes on.\n",
+            "\n",
+            "</details>\n",
+        );
+        let result = render_excerpt(markdown, Themes::OneHalfDark, &limit);
+        assert!(!result.contains("<details>"), "{}", result);
+        assert!(result.contains("Hidden…"), "{}", result);
+
+        limit.chars = Some(99);
+        let result = render_excerpt(markdown, Themes::OneHalfDark, &limit);
+        assert!(result.contains("<details>"), "{}", result);
+        assert!(result.contains("</details>"), "{}", result);
+    }
+
+    #[test]
+    fn test_excerpt_strips_footnote_references() {
+        let mut limit = ExcerptLimit::new();
+        limit.blocks = Some(1);
+
+        let result = render_excerpt(
+            "Claim[^1] stands.\n\nMore.\n\n[^1]: Source.\n",
+            Themes::OneHalfDark,
+            &limit,
+        );
+        assert!(!result.contains("fn-1"), "{}", result);
+        assert!(result.contains("Claim stands.…"), "{}", result);
+    }
+
+    #[test]
+    fn test_render_plain_text_toml_front_matter() {
+        let markdown = "+++\ntitle = \"Hello\"\n+++\n\n# Body\n\nText.\n";
+        assert_eq!(render_plain_text(markdown), "Body\n\nText.");
+    }
+
+    #[test]
+    fn test_task_state_names_are_slugified() {
+        let mut options = RenderOptions::new();
+        options.extended_tasks = true;
+        options.set_task_state('?', Some("1 needs \"review\"".into()));
+        options.set_task_state('!', Some("42".into()));
+        let html = render_md_with_options("- [?] a\n- [!] b\n", Themes::OneHalfDark, &options);
+
+        assert!(html.contains("class=\"task-needs--review-\""), "{}", html);
+        assert!(
+            html.contains("data-task-state=\"needs--review-\""),
+            "{}",
+            html
+        );
+        assert!(html.contains("[!] b"), "{}", html);
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_heading_ids_survive_sanitizer() {
+        let mut options = RenderOptions::new();
+        options.heading_ids = true;
+        let markdown = "# One\n\n## Two\n\n### Three\n\n#### Four\n\n##### Five\n\n###### Six\n";
+        let html = render_md_with_options(markdown, Themes::OneHalfDark, &options);
+
+        for expected in [
+            "<h1 id=\"one\">",
+            "<h2 id=\"two\">",
+            "<h3 id=\"three\">",
+            "<h4 id=\"four\">",
+            "<h5 id=\"five\">",
+            "<h6 id=\"six\">",
+        ] {
+            assert!(html.contains(expected), "missing {}: {}", expected, html);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_rewritten_urls_survive_sanitizer() {
+        let mut options = RenderOptions::new();
+        options.link_base_url = Some("/repo/blob/main/".into());
+        options.image_base_url = Some("https://raw.example.com/main/".into());
+        options.md_link_extension = Some(".html".into());
+        let html = render_md_with_options(
+            "[Next](./next.md#usage) ![Diagram](img/a.png)",
+            Themes::OneHalfDark,
+            &options,
+        );
+
+        for expected in [
+            "href=\"/repo/blob/main/next.html#usage\"",
+            "src=\"https://raw.example.com/main/img/a.png\"",
+        ] {
+            assert!(html.contains(expected), "missing {}: {}", expected, html);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_mentions_survive_sanitizer() {
+        let mut options = RenderOptions::new();
+        options.mention_url_template = Some("https://github.com/{user}".into());
+        options.issue_url_template = Some("https://github.com/acme/app/issues/{number}".into());
+        options.repo_issue_url_template = Some("https://github.com/{repo}/issues/{number}".into());
+        let html = render_md_with_options(
+            "Thanks @alice, fixes #12 and acme/lib#7.",
+            Themes::OneHalfDark,
+            &options,
+        );
+
+        for expected in [
+            "href=\"https://github.com/alice\"",
+            ">@alice</a>",
+            "href=\"https://github.com/acme/app/issues/12\"",
+            ">#12</a>",
+            "href=\"https://github.com/acme/lib/issues/7\"",
+            ">acme/lib#7</a>",
+        ] {
+            assert!(html.contains(expected), "missing {}: {}", expected, html);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sanitize")]
+    fn test_emoji_images_survive_sanitizer() {
+        let mut options = RenderOptions::new();
+        options.emoji = true;
+        options.emoji_image_url_template = Some("https://cdn.example.com/{codepoints}.svg".into());
+        let html = render_md_with_options("Nice :+1:!", Themes::OneHalfDark, &options);
+
+        for expected in [
+            "class=\"emoji\"",
+            "src=\"https://cdn.example.com/1f44d.svg\"",
+            "alt=\"👍\"",
+            "title=\":+1:\"",
+        ] {
+            assert!(html.contains(expected), "missing {}: {}", expected, html);
+        }
+    }
 }
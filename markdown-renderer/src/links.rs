@@ -0,0 +1,538 @@
+//! Link, image and iframe extraction for link checkers.
+
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{Arena, Options, parse_document};
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+use wasm_bindgen::prelude::*;
+
+use crate::ast;
+
+/// A block quote marker, with the indentation before it.
+static QUOTE_MARKER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[ \t]*>[ \t]?").unwrap());
+
+/// A list item marker, on the first line of the item.
+static LIST_MARKER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[ \t]*(?:[-+*]|\d{1,9}[.)])(?:[ \t]+|$)").unwrap());
+
+/// A footnote definition label, on the first line of the definition.
+static FOOTNOTE_MARKER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[ \t]*\[\^[^\]]+\]:[ \t]*").unwrap());
+
+/// The `src` of an `<iframe>` tag.
+static IFRAME_SRC_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<iframe\b[^>]*?\ssrc\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap()
+});
+
+/// What kind of reference a [`Link`] is.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// An inline or reference-style link.
+    Link = "link",
+    /// A `<https://...>` autolink or, with the autolink extension, a bare URL.
+    Autolink = "autolink",
+    Image = "image",
+    /// The `src` of a raw HTML `<iframe>`.
+    Iframe = "iframe",
+    /// A link reference definition.
+    Definition = "definition",
+}
+
+/// A link destination found in a document by [`crate::extract_links`].
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub kind: LinkKind,
+    pub destination: String,
+    /// The link title, empty if there is none.
+    pub title: String,
+    /// The link text, the image alt text or the definition label.
+    pub text: String,
+    /// 1-based source line.
+    pub line: usize,
+    /// 1-based source column.
+    pub column: usize,
+}
+
+/// Collect every link, image, iframe `src` and link reference definition in
+/// the document rooted at `root`, in source order.
+pub(crate) fn extract_links<'a>(root: &'a AstNode<'a>, markdown: &str) -> Vec<Link> {
+    let lines = markdown.lines().collect::<Vec<&str>>();
+    let mut links = Vec::new();
+
+    for node in root.descendants() {
+        let ast = node.data.borrow();
+        let start = ast.sourcepos.start;
+
+        match ast.value {
+            NodeValue::Link(ref link) | NodeValue::Image(ref link) => {
+                let text = ast::text_content(node);
+                let kind = match ast.value {
+                    NodeValue::Image(..) => LinkKind::Image,
+                    _ if is_autolink(&lines, start.line, start.column, &text, &link.url) => {
+                        LinkKind::Autolink
+                    }
+                    _ => LinkKind::Link,
+                };
+
+                links.push(Link {
+                    kind,
+                    destination: link.url.to_string(),
+                    title: link.title.to_string(),
+                    text,
+                    line: start.line,
+                    column: start.column,
+                });
+            }
+            NodeValue::HtmlBlock(ref block) => {
+                iframes(&block.literal, start.line, start.column, &mut links);
+            }
+            NodeValue::HtmlInline(ref html) => {
+                iframes(html, start.line, start.column, &mut links);
+            }
+            _ => {}
+        }
+    }
+
+    definitions(root, &lines, &mut links);
+    links.sort_by_key(|link| (link.line, link.column));
+    links
+}
+
+/// Autolinks show their destination as text and don't start with `[`.
+fn is_autolink(lines: &[&str], line: usize, column: usize, text: &str, url: &str) -> bool {
+    let opening = line
+        .checked_sub(1)
+        .and_then(|i| lines.get(i))
+        .and_then(|line| line.as_bytes().get(column.checked_sub(1)?));
+
+    opening != Some(&b'[') && (url == text || url.strip_prefix("mailto:") == Some(text))
+}
+
+/// Add the `src` of every `<iframe>` in `html`, which starts at `line`:`column`.
+fn iframes(html: &str, line: usize, column: usize, links: &mut Vec<Link>) {
+    for captures in IFRAME_SRC_REGEX.captures_iter(html) {
+        let tag = captures.get(0).unwrap();
+        let src = (1..=3).find_map(|i| captures.get(i)).unwrap();
+
+        let before = &html[..tag.start()];
+        let (line, column) = match before.rfind('\n') {
+            Some(newline) => (line + before.matches('\n').count(), tag.start() - newline),
+            None => (line, column + tag.start()),
+        };
+
+        links.push(Link {
+            kind: LinkKind::Iframe,
+            destination: src.as_str().into(),
+            title: String::new(),
+            text: String::new(),
+            line,
+            column,
+        });
+    }
+}
+
+/// Consecutive source lines holding nothing but link reference definitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DefinitionRun {
+    /// 1-based source lines.
+    pub(crate) lines: RangeInclusive<usize>,
+    /// The byte offset of each line's content, after its container markers.
+    offsets: Vec<usize>,
+    /// The lines' content, joined by newlines.
+    pub(crate) text: String,
+}
+
+impl DefinitionRun {
+    /// The run moved by `delta` lines.
+    pub(crate) fn shifted(&self, delta: isize) -> Self {
+        Self {
+            lines: self.lines.start().saturating_add_signed(delta)
+                ..=self.lines.end().saturating_add_signed(delta),
+            ..self.clone()
+        }
+    }
+}
+
+/// Find the link reference definitions, which comrak resolves and drops while
+/// parsing, in source order.
+///
+/// Definitions can only open a paragraph, so they are the lines the parse
+/// leaves without content: the start of a paragraph its inlines don't reach,
+/// and the lines between the blocks of a container where a paragraph of
+/// nothing but definitions was dropped.
+pub(crate) fn definition_runs<'a>(root: &'a AstNode<'a>, lines: &[&str]) -> Vec<DefinitionRun> {
+    let mut runs = Vec::new();
+
+    for node in root.descendants() {
+        let ast = node.data.borrow();
+        let sourcepos = ast.sourcepos;
+        // The last line of a paragraph or, for a setext heading, of its text.
+        let text_end = match ast.value {
+            NodeValue::Paragraph => Some(sourcepos.end.line),
+            NodeValue::Heading(ref heading) if heading.setext => Some(sourcepos.end.line - 1),
+            _ => None,
+        };
+
+        let (container, numbers) = if let Some(text_end) = text_end {
+            let (Some(parent), Some(first), Some(last)) =
+                (node.parent(), node.first_child(), node.last_child())
+            else {
+                continue;
+            };
+            // The inlines are counted from the end: comrak positions them from
+            // the start of the content left after the definitions.
+            let content = last.data.borrow().sourcepos.end.line
+                - first.data.borrow().sourcepos.start.line
+                + 1;
+            let definitions = (text_end + 1 - sourcepos.start.line).saturating_sub(content);
+            let start = sourcepos.start.line;
+            (parent, (start..start + definitions).collect::<Vec<_>>())
+        } else if is_container(&ast.value) {
+            let gaps = (sourcepos.start.line..=sourcepos.end.line)
+                .filter(|line| {
+                    !node.children().any(|child| {
+                        let child = child.data.borrow().sourcepos;
+                        (child.start.line..=child.end.line).contains(line)
+                    })
+                })
+                .collect::<Vec<_>>();
+            (node, gaps)
+        } else {
+            continue;
+        };
+
+        // Definitions don't span blank lines, so each run of consecutive lines
+        // with content stands on its own.
+        let mut run: Option<DefinitionRun> = None;
+        for number in numbers {
+            let Some(text) = number.checked_sub(1).and_then(|i| lines.get(i)) else {
+                continue;
+            };
+            let offset = content_offset(container, number, text);
+            let content = &text[offset..];
+            let contiguous = run
+                .as_ref()
+                .is_some_and(|run| run.lines.end() + 1 == number);
+            if !contiguous || content.trim().is_empty() {
+                runs.extend(run.take());
+            }
+            if content.trim().is_empty() {
+                continue;
+            }
+
+            let run = run.get_or_insert_with(|| DefinitionRun {
+                lines: number..=number,
+                offsets: Vec::new(),
+                text: String::new(),
+            });
+            if !run.offsets.is_empty() {
+                run.text.push('\n');
+            }
+            run.lines = *run.lines.start()..=number;
+            run.offsets.push(offset);
+            run.text.push_str(content);
+        }
+        runs.extend(run);
+    }
+
+    runs.sort_by_key(|run| *run.lines.start());
+    runs
+}
+
+/// Add the link reference definitions of the document.
+fn definitions<'a>(root: &'a AstNode<'a>, lines: &[&str], links: &mut Vec<Link>) {
+    for run in definition_runs(root, lines) {
+        read_definitions(&run, links);
+    }
+}
+
+/// Blocks whose children are blocks, so that a dropped paragraph leaves a gap
+/// between them.
+fn is_container(value: &NodeValue) -> bool {
+    matches!(
+        value,
+        NodeValue::Document
+            | NodeValue::BlockQuote
+            | NodeValue::MultilineBlockQuote(..)
+            | NodeValue::Alert(..)
+            | NodeValue::Item(..)
+            | NodeValue::TaskItem(..)
+            | NodeValue::FootnoteDefinition(..)
+    )
+}
+
+/// The byte length of the block quote markers, list markers and indentation
+/// that `container` and the containers around it put before their content on
+/// source `line`.
+fn content_offset<'a>(container: &'a AstNode<'a>, line: usize, text: &str) -> usize {
+    let mut containers = container.ancestors().collect::<Vec<_>>();
+    containers.reverse();
+
+    let mut offset = 0;
+    for node in containers {
+        let ast = node.data.borrow();
+        let first_line = ast.sourcepos.start.line == line;
+        let marker = match ast.value {
+            NodeValue::BlockQuote | NodeValue::Alert(..) => {
+                QUOTE_MARKER_REGEX.find(&text[offset..])
+            }
+            NodeValue::Item(..) | NodeValue::TaskItem(..) if first_line => {
+                LIST_MARKER_REGEX.find(&text[offset..])
+            }
+            NodeValue::FootnoteDefinition(..) if first_line => {
+                FOOTNOTE_MARKER_REGEX.find(&text[offset..])
+            }
+            _ => None,
+        };
+        offset += marker.map_or(0, |marker| marker.end());
+    }
+    offset
+}
+
+/// Add the definitions in `run`.
+///
+/// comrak keeps neither the definitions nor where they were, so a definition
+/// is taken to start on each `[label]:` line that the lines before it complete
+/// as definitions, and is resolved by comrak itself from the run's lines from
+/// there on followed by a reference to the label.
+fn read_definitions(run: &DefinitionRun, links: &mut Vec<Link>) {
+    let lines = run.text.split('\n').collect::<Vec<_>>();
+
+    for (index, line) in lines.iter().enumerate() {
+        let Some(label) = definition_label(line) else {
+            continue;
+        };
+        // The line may continue the title of the definition before it.
+        if index > 0 && !only_definitions(&lines[..index].join("\n")) {
+            continue;
+        }
+        let probe = format!("{}\n\n[{}]", lines[index..].join("\n"), label);
+        let Some((destination, title)) = first_link(&probe) else {
+            continue;
+        };
+
+        let indent = line.len() - line.trim_start().len();
+        links.push(Link {
+            kind: LinkKind::Definition,
+            destination,
+            title,
+            text: label.into(),
+            line: run.lines.start() + index,
+            column: run.offsets[index] + indent + 1,
+        });
+    }
+}
+
+/// The label of a line starting `[label]:`.
+fn definition_label(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('[')?;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => return None,
+            ']' => return rest[i + 1..].starts_with(':').then_some(&rest[..i]),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether comrak reads all of `text` as link reference definitions.
+fn only_definitions(text: &str) -> bool {
+    let arena = Arena::new();
+    parse_document(&arena, text, &Options::default())
+        .first_child()
+        .is_none()
+}
+
+/// The destination and title of the first link comrak finds in `markdown`.
+fn first_link(markdown: &str) -> Option<(String, String)> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, markdown, &Options::default());
+    root.descendants()
+        .find_map(|node| match node.data.borrow().value {
+            NodeValue::Link(ref link) => Some((link.url.to_string(), link.title.to_string())),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{Arena, parse_document};
+
+    use crate::OPTIONS;
+
+    fn extract(markdown: &str) -> Vec<(LinkKind, String, usize, usize)> {
+        crate::extract_links(markdown)
+            .into_iter()
+            .map(|link| (link.kind, link.destination, link.line, link.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let markdown = concat!(
+            "See [docs](https://example.com/docs \"Docs\") and <https://example.com>.\n",
+            "\n",
+            "![logo](logo.png) and [ref][r]\n",
+            "\n",
+            "[r]: https://example.com/ref 'Ref'\n",
+            "\n",
+            "```\n",
+            "[not]: https://example.com/code\n",
+            "```\n",
+            "\n",
+            "<div>\n",
+            "<iframe src=\"https://www.youtube.com/embed/x\"></iframe>\n",
+            "</div>\n",
+        );
+
+        assert_eq!(
+            extract(markdown),
+            vec![
+                (LinkKind::Link, "https://example.com/docs".into(), 1, 5),
+                (LinkKind::Autolink, "https://example.com".into(), 1, 49),
+                (LinkKind::Image, "logo.png".into(), 3, 1),
+                (LinkKind::Link, "https://example.com/ref".into(), 3, 23),
+                (LinkKind::Definition, "https://example.com/ref".into(), 5, 1),
+                (
+                    LinkKind::Iframe,
+                    "https://www.youtube.com/embed/x".into(),
+                    12,
+                    1
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_details() {
+        let markdown = "[**bold** text](https://example.com \"Title\")\n";
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &OPTIONS);
+        let links = extract_links(root, markdown);
+
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].title, "Title");
+        assert_eq!(links[0].text, "bold text");
+    }
+
+    #[test]
+    fn test_definitions_from_the_parse() {
+        let markdown = concat!(
+            "+++\n",
+            "[toml]: /front-matter\n",
+            "+++\n",
+            "\n",
+            "> [quote]: /quote\n",
+            "\n",
+            "- item\n",
+            "- [item]: /item\n",
+            "  text\n",
+            "\n",
+            "[title]: /title \"a\n",
+            "b\"\n",
+            "\n",
+            "Text\n",
+            "[not]: /continuation\n",
+        );
+        let links = crate::extract_links(markdown);
+
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| (link.kind, link.destination.as_str(), link.line, link.column))
+                .collect::<Vec<_>>(),
+            vec![
+                (LinkKind::Definition, "/quote", 5, 3),
+                (LinkKind::Definition, "/item", 8, 3),
+                (LinkKind::Definition, "/title", 11, 1),
+            ]
+        );
+        assert_eq!(links[2].title, "a\nb");
+        assert_eq!(links[2].text, "title");
+    }
+
+    #[test]
+    fn test_definitions_match_commonmark_examples() {
+        let definitions = |markdown: &str| {
+            crate::extract_links(markdown)
+                .into_iter()
+                .filter(|link| link.kind == LinkKind::Definition)
+                .map(|link| (link.text, link.destination, link.title, link.line))
+                .collect::<Vec<_>>()
+        };
+        let definition = |text: &str, destination: &str, title: &str, line: usize| {
+            (
+                text.to_string(),
+                destination.to_string(),
+                title.to_string(),
+                line,
+            )
+        };
+
+        assert_eq!(
+            definitions("   [foo]: \n      /url  \n           'the title'  \n\n[foo]\n"),
+            vec![definition("foo", "/url", "the title", 1)]
+        );
+        assert_eq!(
+            definitions("[Foo*bar\\]]:my_(url) 'title (with parens)'\n\n[Foo*bar\\]]\n"),
+            vec![definition(
+                "Foo*bar\\]",
+                "my_(url)",
+                "title (with parens)",
+                1
+            )]
+        );
+        assert_eq!(
+            definitions("[foo]: /url '\ntitle\nline1\nline2\n'\n\n[foo]\n"),
+            vec![definition("foo", "/url", "\ntitle\nline1\nline2\n", 1)]
+        );
+        assert_eq!(
+            definitions("[foo]: /url\\bar\\*baz \"foo\\\"bar\\baz\"\n\n[foo]\n"),
+            vec![definition("foo", "/url\\bar*baz", "foo\"bar\\baz", 1)]
+        );
+        assert_eq!(
+            definitions("[FOO]: /url\n\n[Foo]\n"),
+            vec![definition("FOO", "/url", "", 1)]
+        );
+        assert_eq!(
+            definitions("[foo]: /url\n\"title\" ok\n"),
+            vec![definition("foo", "/url", "", 1)]
+        );
+        assert_eq!(
+            definitions("# [Foo]\n[foo]: /url\n> bar\n"),
+            vec![definition("foo", "/url", "", 2)]
+        );
+        assert_eq!(
+            definitions("[foo]: /url\n===\n[foo]\n"),
+            vec![definition("foo", "/url", "", 1)]
+        );
+        assert_eq!(
+            definitions("[foo]: /foo-url \"foo\"\n[bar]: /bar-url\n  \"bar\"\n[baz]: /baz-url\n"),
+            vec![
+                definition("foo", "/foo-url", "foo", 1),
+                definition("bar", "/bar-url", "bar", 2),
+                definition("baz", "/baz-url", "", 4),
+            ]
+        );
+
+        for markdown in [
+            "[foo]: /url 'title\n\nwith blank line'\n\n[foo]\n",
+            "[foo]:\n\n[foo]\n",
+            "[foo]: /url \"title\" ok\n",
+            "    [foo]: /url \"title\"\n\n[foo]\n",
+            "```\n[foo]: /url\n```\n\n[foo]\n",
+            "Foo\n[bar]: /baz\n\n[bar]\n",
+        ] {
+            assert_eq!(definitions(markdown), vec![], "{:?}", markdown);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Conversion of the comrak AST to [mdast](https://github.com/syntax-tree/mdast) JSON.
+//!
+//! Node types follow mdast and its GFM, math and front matter extensions.
+//! Extensions without an mdast equivalent use their own types: `alert`,
+//! `spoiler`, `underline`, `superscript`, `subscript`, `wikiLink` and the
+//! `description*` nodes.
+
+use comrak::nodes::{AstNode, ListType, NodeValue, Sourcepos, TableAlignment};
+use serde_json::{Map, Value, json};
+
+use crate::{ast, front_matter};
+
+/// Convert `node` and its descendants to an mdast node with unist positions.
+pub(crate) fn to_mdast<'a>(node: &'a AstNode<'a>) -> Value {
+    let ast = node.data.borrow();
+    let (node_type, fields, parent) = match ast.value {
+        NodeValue::Document => ("root", json!({}), true),
+        NodeValue::FrontMatter(ref raw) => {
+            let node_type = match raw.trim_start().starts_with(front_matter::TOML_DELIMITER) {
+                true => "toml",
+                false => "yaml",
+            };
+            let value = front_matter::strip_delimiters(raw).trim_end();
+            (node_type, json!({ "value": value }), false)
+        }
+        NodeValue::BlockQuote | NodeValue::MultilineBlockQuote(..) => {
+            ("blockquote", json!({}), true)
+        }
+        NodeValue::Alert(ref alert) => (
+            "alert",
+            json!({
+                "alertType": ast::alert_type_name(alert.alert_type),
+                "title": alert.title,
+            }),
+            true,
+        ),
+        NodeValue::List(ref list) => {
+            let ordered = list.list_type == ListType::Ordered;
+            (
+                "list",
+                json!({
+                    "ordered": ordered,
+                    "start": ordered.then_some(list.start),
+                    "spread": !list.tight,
+                }),
+                true,
+            )
+        }
+        NodeValue::Item(..) => (
+            "listItem",
+            json!({ "spread": !in_tight_list(node), "checked": null }),
+            true,
+        ),
+        NodeValue::TaskItem(ref task) => (
+            "listItem",
+            json!({ "spread": !in_tight_list(node), "checked": task.symbol.is_some() }),
+            true,
+        ),
+        NodeValue::DescriptionList => ("descriptionList", json!({}), true),
+        NodeValue::DescriptionItem(..) => ("descriptionItem", json!({}), true),
+        NodeValue::DescriptionTerm => ("descriptionTerm", json!({}), true),
+        NodeValue::DescriptionDetails => ("descriptionDetails", json!({}), true),
+        NodeValue::CodeBlock(ref block) => {
+            let lang = ast::code_block_lang(&block.info);
+            let meta = block.info.trim()[lang.map_or(0, str::len)..].trim();
+            let meta = (!meta.is_empty()).then_some(meta);
+            let value = block.literal.strip_suffix('\n').unwrap_or(&block.literal);
+
+            match lang {
+                Some("math") => ("math", json!({ "meta": meta, "value": value }), false),
+                _ => (
+                    "code",
+                    json!({ "lang": lang, "meta": meta, "value": value }),
+                    false,
+                ),
+            }
+        }
+        NodeValue::HtmlBlock(ref block) => {
+            let value = block.literal.strip_suffix('\n').unwrap_or(&block.literal);
+            ("html", json!({ "value": value }), false)
+        }
+        NodeValue::Paragraph => ("paragraph", json!({}), true),
+        NodeValue::Heading(ref heading) => ("heading", json!({ "depth": heading.level }), true),
+        NodeValue::ThematicBreak => ("thematicBreak", json!({}), false),
+        NodeValue::FootnoteDefinition(ref footnote) => (
+            "footnoteDefinition",
+            json!({ "identifier": footnote.name.to_lowercase(), "label": footnote.name }),
+            true,
+        ),
+        NodeValue::Table(ref table) => {
+            let align = table
+                .alignments
+                .iter()
+                .map(|alignment| match alignment {
+                    TableAlignment::Left => Some("left"),
+                    TableAlignment::Center => Some("center"),
+                    TableAlignment::Right => Some("right"),
+                    TableAlignment::None => None,
+                })
+                .collect::<Vec<_>>();
+            ("table", json!({ "align": align }), true)
+        }
+        NodeValue::TableRow(..) => ("tableRow", json!({}), true),
+        NodeValue::TableCell => ("tableCell", json!({}), true),
+        NodeValue::Text(ref text) => ("text", json!({ "value": text }), false),
+        // Merged into the text around it, as in mdast.
+        NodeValue::SoftBreak => ("text", json!({ "value": "\n" }), false),
+        NodeValue::LineBreak => ("break", json!({}), false),
+        NodeValue::Code(ref code) => ("inlineCode", json!({ "value": code.literal }), false),
+        NodeValue::HtmlInline(ref html) | NodeValue::Raw(ref html) => {
+            ("html", json!({ "value": html }), false)
+        }
+        NodeValue::Emph => ("emphasis", json!({}), true),
+        NodeValue::Strong => ("strong", json!({}), true),
+        NodeValue::Strikethrough => ("delete", json!({}), true),
+        NodeValue::Superscript => ("superscript", json!({}), true),
+        NodeValue::Subscript => ("subscript", json!({}), true),
+        NodeValue::Underline => ("underline", json!({}), true),
+        NodeValue::SpoileredText => ("spoiler", json!({}), true),
+        NodeValue::Link(ref link) => (
+            "link",
+            json!({ "url": link.url, "title": non_empty(&link.title) }),
+            true,
+        ),
+        NodeValue::Image(ref link) => (
+            "image",
+            json!({
+                "url": link.url,
+                "title": non_empty(&link.title),
+                "alt": ast::text_content(node),
+            }),
+            false,
+        ),
+        NodeValue::WikiLink(ref link) => ("wikiLink", json!({ "url": link.url }), true),
+        NodeValue::FootnoteReference(ref footnote) => (
+            "footnoteReference",
+            json!({ "identifier": footnote.name.to_lowercase(), "label": footnote.name }),
+            false,
+        ),
+        NodeValue::Math(ref math) => match math.display_math {
+            true => (
+                "math",
+                json!({ "meta": null, "value": math.literal }),
+                false,
+            ),
+            false => ("inlineMath", json!({ "value": math.literal }), false),
+        },
+        _ => ("unknown", json!({}), true),
+    };
+
+    let mut object = Map::new();
+    object.insert("type".into(), node_type.into());
+    if let Value::Object(fields) = fields {
+        object.extend(fields);
+    }
+
+    if parent {
+        let children = merge_text(node.children().map(to_mdast));
+        object.insert("children".into(), children.into());
+    }
+    object.insert("position".into(), position(ast.sourcepos));
+
+    Value::Object(object)
+}
+
+/// `children` with adjacent text nodes merged into one.
+fn merge_text(children: impl Iterator<Item = Value>) -> Vec<Value> {
+    let mut merged: Vec<Value> = Vec::new();
+
+    for child in children {
+        if let Some(last) = merged.last_mut()
+            && last["type"] == "text"
+            && child["type"] == "text"
+        {
+            let value = format!(
+                "{}{}",
+                last["value"].as_str().unwrap_or_default(),
+                child["value"].as_str().unwrap_or_default()
+            );
+            last["value"] = value.into();
+            last["position"]["end"] = child["position"]["end"].clone();
+            continue;
+        }
+        merged.push(child);
+    }
+
+    merged
+}
+
+/// A unist position. Unlike comrak's, unist end columns are exclusive.
+fn position(sourcepos: Sourcepos) -> Value {
+    json!({
+        "start": { "line": sourcepos.start.line, "column": sourcepos.start.column },
+        "end": { "line": sourcepos.end.line, "column": sourcepos.end.column + 1 },
+    })
+}
+
+fn in_tight_list<'a>(item: &'a AstNode<'a>) -> bool {
+    item.parent().is_some_and(
+        |list| matches!(list.data.borrow().value, NodeValue::List(ref list) if list.tight),
+    )
+}
+
+fn non_empty(text: &str) -> Option<&str> {
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mdast(markdown: &str) -> Value {
+        serde_json::from_str(&crate::parse_md_to_json(markdown)).unwrap()
+    }
+
+    #[test]
+    fn test_mdast_blocks_and_positions() {
+        let tree = mdast("# Hello *world*\n\n```rust title=main.rs\nfn main() {}\n```\n");
+
+        assert_eq!(tree["type"], "root");
+        let heading = &tree["children"][0];
+        assert_eq!(heading["type"], "heading");
+        assert_eq!(heading["depth"], 1);
+        assert_eq!(
+            heading["children"][0],
+            json!({
+                "type": "text",
+                "value": "Hello ",
+                "position": {
+                    "start": { "line": 1, "column": 3 },
+                    "end": { "line": 1, "column": 9 },
+                },
+            })
+        );
+        assert_eq!(heading["children"][1]["type"], "emphasis");
+
+        let code = &tree["children"][1];
+        assert_eq!(code["type"], "code");
+        assert_eq!(code["lang"], "rust");
+        assert_eq!(code["meta"], "title=main.rs");
+        assert_eq!(code["value"], "fn main() {}");
+        assert!(code.get("children").is_none());
+        assert_eq!(code["position"]["start"]["line"], 3);
+        assert_eq!(code["position"]["end"]["line"], 5);
+    }
+
+    #[test]
+    fn test_mdast_extensions() {
+        let tree = mdast(concat!(
+            "---\n",
+            "title: Post\n",
+            "---\n",
+            "\n",
+            "- [x] done\n",
+            "- todo\n",
+            "\n",
+            "| a | b |\n",
+            "|:--|--:|\n",
+            "| 1 | 2 |\n",
+            "\n",
+            "Math $x^2$ and ~~old~~ and ||secret||\n",
+        ));
+        let children = tree["children"].as_array().unwrap();
+
+        assert_eq!(children[0]["type"], "yaml");
+        assert_eq!(children[0]["value"], "title: Post");
+
+        let items = &children[1]["children"];
+        assert_eq!(children[1]["spread"], false);
+        assert_eq!(items[0]["checked"], true);
+        assert_eq!(items[1]["checked"], Value::Null);
+
+        assert_eq!(children[2]["type"], "table");
+        assert_eq!(children[2]["align"], json!(["left", "right"]));
+        assert_eq!(
+            children[2]["children"][1]["children"][0]["type"],
+            "tableCell"
+        );
+
+        let inline = children[3]["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["type"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inline,
+            vec!["text", "inlineMath", "text", "delete", "text", "spoiler"]
+        );
+    }
+
+    #[test]
+    fn test_mdast_merges_soft_breaks_and_display_math() {
+        let tree = mdast("First *line*\nsecond line\nthird $$x^2$$\n");
+        let children = tree["children"][0]["children"].as_array().unwrap();
+
+        assert_eq!(children.len(), 4);
+        assert_eq!(children[0]["value"], "First ");
+        assert_eq!(children[1]["type"], "emphasis");
+        assert_eq!(children[2]["type"], "text");
+        assert_eq!(children[2]["value"], "\nsecond line\nthird ");
+        assert_eq!(children[2]["position"]["start"]["line"], 1);
+        assert_eq!(children[2]["position"]["end"]["line"], 3);
+        assert_eq!(children[3]["type"], "math");
+        assert_eq!(children[3]["value"], "x^2");
+    }
+
+    #[test]
+    fn test_mdast_toml_front_matter() {
+        let tree = mdast("+++\ntitle = \"Post\"\n+++\n\n# Hello\n");
+        let children = tree["children"].as_array().unwrap();
+
+        assert_eq!(children[0]["type"], "toml");
+        assert_eq!(children[0]["value"], "title = \"Post\"");
+        assert_eq!(children[1]["type"], "heading");
+        assert_eq!(children[1]["position"]["start"]["line"], 5);
+    }
+}
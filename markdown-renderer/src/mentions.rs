@@ -0,0 +1,161 @@
+//! `@username`, `#123` and `org/repo#123` autolinking.
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+use wasm_bindgen::prelude::*;
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+
+/// A mention or issue reference, preceded by the start of the text or a
+/// character that can't be part of a word, path or email address.
+static REFERENCE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(^|[^\w/@.])(?:@([A-Za-z0-9][A-Za-z0-9-]*)|(?:([A-Za-z0-9][\w.-]*/[\w.-]+))?#(\d+))\b",
+    )
+    .unwrap()
+});
+
+/// An issue reference found by [`RenderOptions::issue_url_template`] or
+/// [`RenderOptions::repo_issue_url_template`] linking.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueReference {
+    /// `org/repo` for cross-repository references.
+    pub repo: Option<String>,
+    pub number: u64,
+}
+
+/// The mentions and issue references linked in a document.
+#[derive(Debug, Default)]
+pub(crate) struct References {
+    pub(crate) mentions: Vec<String>,
+    pub(crate) issues: Vec<IssueReference>,
+}
+
+/// Whether `options` has a template for any kind of reference.
+pub(crate) fn links_references(options: &RenderOptions) -> bool {
+    options.mention_url_template.is_some()
+        || options.issue_url_template.is_some()
+        || options.repo_issue_url_template.is_some()
+}
+
+/// Turn the mentions and issue references in the document's text into links.
+///
+/// Only text nodes are searched, so code spans, code blocks, math and raw HTML
+/// are never linked, and neither is text that is already inside a link.
+pub(crate) fn link_references<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+) -> References {
+    let mut references = References::default();
+
+    for node in root.descendants().collect::<Vec<_>>() {
+        let text = match node.data.borrow().value {
+            NodeValue::Text(ref text) if !in_link(node) => text.to_string(),
+            _ => continue,
+        };
+
+        let mut rest = 0;
+        for captures in REFERENCE_REGEX.captures_iter(&text) {
+            let Some(url) = reference_url(&captures, options, &mut references) else {
+                continue;
+            };
+
+            let start = captures.get(1).unwrap().end();
+            let end = captures.get(0).unwrap().end();
+            let open = format!("<a href=\"{}\">", ast::escape_html(&url));
+
+            insert_text(arena, node, &text[rest..start]);
+            node.insert_before(arena.alloc(NodeValue::Raw(open).into()));
+            insert_text(arena, node, &text[start..end]);
+            node.insert_before(arena.alloc(NodeValue::Raw("</a>".into()).into()));
+            rest = end;
+        }
+
+        if rest > 0
+            && let NodeValue::Text(ref mut literal) = node.data.borrow_mut().value
+        {
+            *literal = text[rest..].to_string().into();
+        }
+    }
+
+    references
+}
+
+/// The URL for a matched reference if its kind has a template, recording it
+/// in `references`.
+fn reference_url(
+    captures: &Captures,
+    options: &RenderOptions,
+    references: &mut References,
+) -> Option<String> {
+    if let Some(user) = captures.get(2) {
+        let template = options.mention_url_template.as_ref()?;
+        let user = user.as_str();
+        if !references.mentions.iter().any(|mention| mention == user) {
+            references.mentions.push(user.into());
+        }
+
+        return Some(template.replace("{user}", user));
+    }
+
+    let number = &captures[4];
+    let repo = captures.get(3).map(|repo| repo.as_str());
+    let url = match repo {
+        Some(repo) => options
+            .repo_issue_url_template
+            .as_ref()?
+            .replace("{repo}", repo),
+        None => options.issue_url_template.clone()?,
+    };
+
+    let reference = IssueReference {
+        repo: repo.map(String::from),
+        number: number.parse().ok()?,
+    };
+    if !references.issues.contains(&reference) {
+        references.issues.push(reference);
+    }
+
+    Some(url.replace("{number}", number))
+}
+
+fn insert_text<'a>(arena: &'a Arena<'a>, node: &'a AstNode<'a>, text: &str) {
+    if !text.is_empty() {
+        node.insert_before(arena.alloc(NodeValue::Text(text.to_string().into()).into()));
+    }
+}
+
+fn in_link<'a>(node: &'a AstNode<'a>) -> bool {
+    node.ancestors().any(|ancestor| {
+        matches!(
+            ancestor.data.borrow().value,
+            NodeValue::Link(..) | NodeValue::WikiLink(..) | NodeValue::Image(..)
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reference_regex() {
+        let matches = |text: &str| {
+            REFERENCE_REGEX
+                .captures_iter(text)
+                .map(|captures| captures[0][captures[1].len()..].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            matches("@alice, see #12 and org/repo#3 (@bob-2)"),
+            vec!["@alice", "#12", "org/repo#3", "@bob-2"]
+        );
+        assert!(matches("mail me@example.com").is_empty());
+        assert!(matches("issue#12 #12a a/b/c#1").is_empty());
+    }
+}
@@ -0,0 +1,434 @@
+//! Runtime-configurable render options.
+
+use comrak::Options;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::front_matter;
+
+/// Maximum number of distinct option sets kept in the comrak options cache.
+const OPTIONS_CACHE_CAPACITY: usize = 32;
+
+/// Extension and render toggles for [`crate::render_md_with_options`].
+///
+/// The defaults match the options used by [`crate::render_md`].
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// GFM tables.
+    pub table: bool,
+    /// Wrap tables in a `<div class="table-wrapper">` so wide ones can scroll,
+    /// write alignment as `align-left`, `align-center` and `align-right`
+    /// classes instead of the `align` attribute, and give each header cell a
+    /// `data-sort-type` of `number`, `date` or `text` inferred from its column.
+    pub table_wrappers: bool,
+    /// Turn a `Table: caption` paragraph right after a table, or right before
+    /// it, into the table's `<caption>`, as in pandoc.
+    pub table_captions: bool,
+    /// MultiMarkdown-style cell spans: a cell followed by `||` spans the next
+    /// column as well, and a cell holding only `^^` is merged into the cell
+    /// above it.
+    pub table_spans: bool,
+    /// Render ```` ```csv ```` and ```` ```tsv ```` fences as tables. The first
+    /// row is the header unless the info string says `header=false`, and
+    /// `delimiter=;` sets another delimiter. Blocks that don't parse stay
+    /// highlighted code, with a warning.
+    pub csv_tables: bool,
+    /// GFM task list items (`- [ ]` / `- [x]`).
+    pub tasklist: bool,
+    /// Render task checkboxes enabled, with `data-task-line` (1-based source
+    /// line) and `data-task-index` (0-based, for [`crate::toggle_task`]).
+    /// Not supported by [`crate::StreamRenderer`].
+    pub interactive_tasks: bool,
+    /// Extra task states beyond `[ ]` and `[x]`, by default `[-]` cancelled,
+    /// `[~]` in progress, `[?]` question and `[!]` important. Set the states
+    /// with [`Self::set_task_state`]; they render as `data-task-state` and a
+    /// `task-<state>` class on the list item.
+    pub extended_tasks: bool,
+    /// GitHub-style alerts (`> [!NOTE]`).
+    pub alerts: bool,
+    /// Obsidian-style foldable alerts: `> [!TIP]-` renders collapsed and
+    /// `> [!TIP]+` expanded, as a `<details>`.
+    pub foldable_alerts: bool,
+    /// `__underline__`.
+    pub underline: bool,
+    /// `~~strikethrough~~`.
+    pub strikethrough: bool,
+    /// `||spoiler||`.
+    pub spoiler: bool,
+    /// `^superscript^`.
+    pub superscript: bool,
+    /// `~subscript~`. Takes over single tildes, so strikethrough needs `~~`.
+    pub subscript: bool,
+    /// Math in ```` ```math ```` fences and `` $`...`$ `` spans.
+    pub math_code: bool,
+    /// Math in `$...$` and `$$...$$`.
+    pub math_dollars: bool,
+    /// GFM footnotes (`[^1]`) and inline footnotes (`^[A note.]`), with
+    /// back-references and a `data-footnote-content` preview on each reference.
+    pub footnotes: bool,
+    /// GFM autolinks for bare URLs and emails.
+    pub autolink: bool,
+    /// Definition lists.
+    pub description_lists: bool,
+    /// Strip a leading `---` (YAML) or `+++` (TOML) front matter block from the
+    /// output and parse it into [`crate::RenderResult::front_matter`].
+    pub front_matter: bool,
+    /// Pass raw HTML through instead of replacing it with a placeholder comment.
+    pub raw_html: bool,
+    /// Render soft line breaks as `<br />`.
+    pub hardbreaks: bool,
+    /// Convert straight quotes, dashes and ellipses to their typographic forms.
+    pub smart: bool,
+    /// Give every heading a unique GitHub-style `id` slug.
+    pub heading_ids: bool,
+    /// Replace paragraphs containing only `[[toc]]` with a `<nav class="toc">` list.
+    pub toc_placeholder: bool,
+    /// Emit `data-sourcepos="line:col-line:col"` on block elements.
+    pub sourcepos: bool,
+    /// Treat the input as partial output that is still being written: close
+    /// unfinished constructs at the end and mark the last block with
+    /// `data-streaming="true"`.
+    pub streaming: bool,
+    /// Base URL that relative link destinations are resolved against, as a
+    /// browser would: end it with `/` to resolve inside that directory.
+    pub link_base_url: Option<String>,
+    /// Base URL that relative image sources are resolved against, like
+    /// [`Self::link_base_url`].
+    pub image_base_url: Option<String>,
+    /// Replace the `.md` extension of relative link destinations, e.g. with
+    /// `.html`, or drop it with an empty string.
+    pub md_link_extension: Option<String>,
+    /// Parse `[[Page Name]]` and `[[Page Name|label]]` wikilinks.
+    pub wikilinks: bool,
+    /// Link `@username` mentions to this URL, with `{user}` replaced by the name.
+    pub mention_url_template: Option<String>,
+    /// Link `#123` issue references to this URL, with `{number}` replaced.
+    pub issue_url_template: Option<String>,
+    /// Link `org/repo#123` issue references to this URL, with `{repo}` and
+    /// `{number}` replaced.
+    pub repo_issue_url_template: Option<String>,
+    /// Replace `:shortcode:` emoji, such as `:tada:`, with Unicode emoji.
+    pub emoji: bool,
+    /// Render emoji as `<img class="emoji">` from this URL instead, with
+    /// `{codepoints}` replaced by the lowercase hex codepoints joined by `-`
+    /// (e.g. `1f389`) and `{shortcode}` by the shortcode without colons.
+    pub emoji_image_url_template: Option<String>,
+    /// `:::type [title]` ... `:::` container blocks. Types on
+    /// [`Self::container_types`] render as `<div class="container container-type">`,
+    /// `details` as `<details>` with the title as its summary, and other types
+    /// as a plain `<div>`.
+    pub containers: bool,
+    /// The container types given their own class.
+    pub container_types: Vec<String>,
+    url_rewriter: Option<JsCallback>,
+    wikilink_resolver: Option<WikiLinkResolver>,
+    alert_types: BTreeMap<String, AlertStyle>,
+    task_states: BTreeMap<char, String>,
+}
+
+/// The title and icon of an alert type, set with [`RenderOptions::set_alert_type`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AlertStyle {
+    /// Replaces the default title.
+    pub(crate) title: Option<String>,
+    /// Inline HTML put before the title.
+    pub(crate) icon: Option<String>,
+}
+
+/// How wikilink page names are turned into URLs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum WikiLinkResolver {
+    /// A JS `(page) => url` function; anything but a string means the page is missing.
+    Callback(JsCallback),
+    /// Known pages and their URLs.
+    Pages(BTreeMap<String, String>),
+}
+
+/// A JS function, compared by identity.
+#[derive(Debug, Clone)]
+pub(crate) struct JsCallback(pub(crate) js_sys::Function);
+
+impl PartialEq for JsCallback {
+    fn eq(&self, other: &Self) -> bool {
+        JsValue::eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for JsCallback {}
+
+/// The settings that go into the comrak [`Options`], which key the options
+/// cache of [`RenderOptions::comrak_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ComrakSettings {
+    table: bool,
+    tasklist: bool,
+    alerts: bool,
+    underline: bool,
+    strikethrough: bool,
+    spoiler: bool,
+    superscript: bool,
+    subscript: bool,
+    math_code: bool,
+    math_dollars: bool,
+    footnotes: bool,
+    autolink: bool,
+    description_lists: bool,
+    wikilinks: bool,
+    front_matter: bool,
+    smart: bool,
+    extended_tasks: bool,
+    hardbreaks: bool,
+    raw_html: bool,
+    sourcepos: bool,
+}
+
+impl ComrakSettings {
+    fn to_comrak(self) -> Options<'static> {
+        let mut options = Options::default();
+        options.extension.table = self.table;
+        options.extension.tasklist = self.tasklist;
+        options.extension.alerts = self.alerts;
+        options.extension.underline = self.underline;
+        options.extension.strikethrough = self.strikethrough;
+        options.extension.spoiler = self.spoiler;
+        options.extension.superscript = self.superscript;
+        options.extension.subscript = self.subscript;
+        options.extension.math_code = self.math_code;
+        options.extension.math_dollars = self.math_dollars;
+        options.extension.footnotes = self.footnotes;
+        options.extension.inline_footnotes = self.footnotes;
+        options.extension.autolink = self.autolink;
+        options.extension.description_lists = self.description_lists;
+        options.extension.wikilinks_title_after_pipe = self.wikilinks;
+        if self.front_matter {
+            options.extension.front_matter_delimiter = Some(front_matter::YAML_DELIMITER.into());
+        }
+        options.parse.smart = self.smart;
+        options.parse.relaxed_tasklist_matching = self.extended_tasks;
+        options.render.hardbreaks = self.hardbreaks;
+        options.render.r#unsafe = self.raw_html;
+        options.render.sourcepos = self.sourcepos;
+
+        options
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            table: true,
+            table_wrappers: false,
+            table_captions: false,
+            table_spans: false,
+            csv_tables: false,
+            tasklist: true,
+            interactive_tasks: false,
+            extended_tasks: false,
+            alerts: true,
+            foldable_alerts: false,
+            underline: true,
+            strikethrough: true,
+            spoiler: true,
+            superscript: true,
+            subscript: false,
+            math_code: true,
+            math_dollars: true,
+            footnotes: true,
+            autolink: false,
+            description_lists: false,
+            front_matter: true,
+            raw_html: true,
+            hardbreaks: false,
+            smart: false,
+            heading_ids: false,
+            toc_placeholder: false,
+            sourcepos: false,
+            streaming: false,
+            link_base_url: None,
+            image_base_url: None,
+            md_link_extension: None,
+            wikilinks: false,
+            mention_url_template: None,
+            issue_url_template: None,
+            repo_issue_url_template: None,
+            emoji: false,
+            emoji_image_url_template: None,
+            containers: false,
+            container_types: [
+                "note",
+                "info",
+                "tip",
+                "important",
+                "warning",
+                "caution",
+                "danger",
+                "details",
+                "tabs",
+            ]
+            .map(String::from)
+            .to_vec(),
+            url_rewriter: None,
+            wikilink_resolver: None,
+            alert_types: BTreeMap::new(),
+            task_states: [
+                ('-', "cancelled"),
+                ('~', "in-progress"),
+                ('?', "question"),
+                ('!', "important"),
+            ]
+            .into_iter()
+            .map(|(symbol, state)| (symbol, state.to_string()))
+            .collect(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl RenderOptions {
+    /// Create options with the same defaults as [`crate::render_md`].
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set a `(kind, url) => url` function that rewrites every link and image
+    /// destination before the HTML is written. `kind` is `"link"` or
+    /// `"image"`; returning anything but a string keeps the URL.
+    pub fn set_url_rewriter(&mut self, rewriter: Option<js_sys::Function>) {
+        self.url_rewriter = rewriter.map(JsCallback);
+    }
+
+    /// Resolve wikilinks with a `(page) => url` function. Pages it returns
+    /// anything but a string for are rendered as missing.
+    pub fn set_wikilink_resolver(&mut self, resolver: Option<js_sys::Function>) {
+        self.wikilink_resolver =
+            resolver.map(|resolver| WikiLinkResolver::Callback(JsCallback(resolver)));
+    }
+
+    /// Resolve wikilinks from a JSON object mapping page names to URLs. Pages
+    /// not in the map are rendered as missing.
+    pub fn set_wikilink_pages(&mut self, pages: &str) -> Result<(), JsError> {
+        let pages = serde_json::from_str::<BTreeMap<String, String>>(pages)?;
+        self.wikilink_resolver = Some(WikiLinkResolver::Pages(pages));
+        Ok(())
+    }
+
+    /// Register an extra alert type, used as `> [!name]`, or customize one of
+    /// the built-in five. `title` replaces the default title, e.g. to localize
+    /// it, and `icon` is inline HTML such as an `<svg>` put before the title.
+    pub fn set_alert_type(&mut self, name: &str, title: Option<String>, icon: Option<String>) {
+        self.alert_types
+            .insert(name.to_lowercase(), AlertStyle { title, icon });
+    }
+
+    /// Map the task symbol in `[symbol]` to a state name for
+    /// [`Self::extended_tasks`], or remove the symbol with `None`.
+    ///
+    /// The name is used in the `task-{state}` class, so it's turned into a
+    /// class-safe slug: characters other than letters, digits, `-` and `_`
+    /// become `-`, and anything before the first letter is dropped. A name
+    /// without letters removes the symbol.
+    pub fn set_task_state(&mut self, symbol: char, state: Option<String>) {
+        match state.map(|state| slugify_state(&state)) {
+            Some(state) if !state.is_empty() => self.task_states.insert(symbol, state),
+            _ => self.task_states.remove(&symbol),
+        };
+    }
+}
+
+/// Turn a task state name into one matching `[A-Za-z][\w-]*`.
+fn slugify_state(state: &str) -> String {
+    state
+        .trim_start_matches(|c: char| !c.is_ascii_alphabetic())
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '_' {
+            true => c,
+            false => '-',
+        })
+        .collect()
+}
+
+impl RenderOptions {
+    pub(crate) fn url_rewriter(&self) -> Option<&js_sys::Function> {
+        self.url_rewriter.as_ref().map(|rewriter| &rewriter.0)
+    }
+
+    pub(crate) fn wikilink_resolver(&self) -> Option<&WikiLinkResolver> {
+        self.wikilink_resolver.as_ref()
+    }
+
+    pub(crate) fn alert_types(&self) -> &BTreeMap<String, AlertStyle> {
+        &self.alert_types
+    }
+
+    /// The state of a task written as `[symbol]`, when extended tasks are on.
+    pub(crate) fn task_state(&self, symbol: char) -> Option<&str> {
+        match self.extended_tasks {
+            true => self.task_states.get(&symbol).map(String::as_str),
+            false => None,
+        }
+    }
+
+    /// Build the comrak [`Options`] for these settings.
+    pub(crate) fn to_comrak(&self) -> Options<'static> {
+        self.comrak_settings().to_comrak()
+    }
+
+    /// Get the comrak [`Options`] for these settings, building them on first use.
+    ///
+    /// Built options are cached per thread, keyed by the values that go into
+    /// them.
+    pub(crate) fn comrak_options(&self) -> Rc<Options<'static>> {
+        thread_local! {
+            static CACHE: RefCell<HashMap<ComrakSettings, Rc<Options<'static>>>> =
+                RefCell::new(HashMap::new());
+        }
+
+        let settings = self.comrak_settings();
+        CACHE.with_borrow_mut(|cache| {
+            if let Some(options) = cache.get(&settings) {
+                return Rc::clone(options);
+            }
+
+            // Option sets are normally few and long-lived, so just start over
+            // rather than tracking recency.
+            if cache.len() >= OPTIONS_CACHE_CAPACITY {
+                cache.clear();
+            }
+
+            let options = Rc::new(settings.to_comrak());
+            cache.insert(settings, Rc::clone(&options));
+            options
+        })
+    }
+
+    fn comrak_settings(&self) -> ComrakSettings {
+        ComrakSettings {
+            table: self.table,
+            tasklist: self.tasklist,
+            alerts: self.alerts,
+            underline: self.underline,
+            strikethrough: self.strikethrough,
+            spoiler: self.spoiler,
+            superscript: self.superscript,
+            subscript: self.subscript,
+            math_code: self.math_code,
+            math_dollars: self.math_dollars,
+            footnotes: self.footnotes,
+            autolink: self.autolink,
+            description_lists: self.description_lists,
+            wikilinks: self.wikilinks,
+            front_matter: self.front_matter,
+            smart: self.smart,
+            extended_tasks: self.extended_tasks,
+            hardbreaks: self.hardbreaks,
+            raw_html: self.raw_html,
+            sourcepos: self.sourcepos,
+        }
+    }
+}
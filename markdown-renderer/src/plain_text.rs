@@ -0,0 +1,200 @@
+//! Plain-text rendering for search indexes, notifications and previews.
+//!
+//! Blocks are separated by blank lines. Code and math keep their source,
+//! links and images are replaced by their text, list items keep a simple
+//! marker and table rows become tab-separated lines. Raw HTML, front matter
+//! and thematic breaks are dropped.
+
+use comrak::nodes::{AstNode, ListType, NodeValue};
+
+/// Render the document rooted at `root` as readable plain text.
+pub(crate) fn plain_text<'a>(root: &'a AstNode<'a>) -> String {
+    let mut blocks = Vec::new();
+    for child in root.children() {
+        block_text(child, &mut blocks);
+    }
+
+    blocks.join("\n\n")
+}
+
+fn block_text<'a>(node: &'a AstNode<'a>, blocks: &mut Vec<String>) {
+    let text = match node.data.borrow().value {
+        NodeValue::Paragraph | NodeValue::Heading(..) | NodeValue::DescriptionTerm => {
+            inline_text(node)
+        }
+        NodeValue::CodeBlock(ref block) => block.literal.trim_end_matches('\n').to_string(),
+        NodeValue::List(..) => list_text(node, 0),
+        NodeValue::Table(..) => node
+            .children()
+            .map(|row| {
+                row.children()
+                    .map(inline_text)
+                    .collect::<Vec<String>>()
+                    .join("\t")
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+        NodeValue::FrontMatter(..) | NodeValue::HtmlBlock(..) | NodeValue::ThematicBreak => {
+            return;
+        }
+        _ => {
+            for child in node.children() {
+                block_text(child, blocks);
+            }
+            return;
+        }
+    };
+
+    if !text.trim().is_empty() {
+        blocks.push(text);
+    }
+}
+
+/// A list with one line per item, nested lists indented by two spaces per level.
+fn list_text<'a>(list: &'a AstNode<'a>, depth: usize) -> String {
+    let (ordered, start) = match list.data.borrow().value {
+        NodeValue::List(ref list) => (list.list_type == ListType::Ordered, list.start),
+        _ => (false, 1),
+    };
+    let indent = "  ".repeat(depth);
+    let mut lines = Vec::new();
+
+    for (index, item) in list.children().enumerate() {
+        let mut marker = match ordered {
+            true => format!("{}. ", start + index),
+            false => String::from("- "),
+        };
+        if let NodeValue::TaskItem(ref task) = item.data.borrow().value {
+            marker.push_str(if task.symbol.is_some() {
+                "[x] "
+            } else {
+                "[ ] "
+            });
+        }
+
+        let mut first = true;
+        for child in item.children() {
+            if matches!(child.data.borrow().value, NodeValue::List(..)) {
+                lines.push(list_text(child, depth + 1));
+                continue;
+            }
+
+            let mut blocks = Vec::new();
+            block_text(child, &mut blocks);
+            for line in blocks.iter().flat_map(|block| block.lines()) {
+                match first {
+                    true => lines.push(format!("{}{}{}", indent, marker, line)),
+                    false => lines.push(format!("{}  {}", indent, line)),
+                }
+                first = false;
+            }
+        }
+
+        if first {
+            lines.push(format!("{}{}", indent, marker.trim_end()));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// The text of an inline container. Soft breaks become spaces, hard breaks
+/// newlines.
+fn inline_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+
+    for descendant in node.descendants() {
+        match descendant.data.borrow().value {
+            NodeValue::Text(ref literal) => text.push_str(literal),
+            NodeValue::Code(ref code) => text.push_str(&code.literal),
+            NodeValue::Math(ref math) => text.push_str(&math.literal),
+            NodeValue::SoftBreak => text.push(' '),
+            NodeValue::LineBreak => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{Arena, parse_document};
+
+    use crate::OPTIONS;
+
+    fn render(markdown: &str) -> String {
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &OPTIONS);
+        plain_text(root)
+    }
+
+    #[test]
+    fn test_plain_text_blocks() {
+        let markdown = concat!(
+            "---\n",
+            "title: Post\n",
+            "---\n",
+            "\n",
+            "# Hello *world*\n",
+            "\n",
+            "See [the docs](https://example.com) and ![a cat](cat.png).\n",
+            "Energy is $E = mc^2$.\n",
+            "\n",
+            "```rust\n",
+            "fn main() {\n",
+            "    println!(\"hi\");\n",
+            "}\n",
+            "```\n",
+            "\n",
+            "<div>dropped</div>\n",
+            "\n",
+            "***\n",
+            "\n",
+            "> quoted\n",
+        );
+
+        assert_eq!(
+            render(markdown),
+            concat!(
+                "Hello world\n",
+                "\n",
+                "See the docs and a cat. Energy is E = mc^2.\n",
+                "\n",
+                "fn main() {\n",
+                "    println!(\"hi\");\n",
+                "}\n",
+                "\n",
+                "quoted",
+            )
+        );
+    }
+
+    #[test]
+    fn test_plain_text_lists_and_tables() {
+        let markdown = concat!(
+            "1. first\n",
+            "2. second\n",
+            "   - [x] done\n",
+            "   - [ ] todo\n",
+            "\n",
+            "| Name | Value |\n",
+            "|------|-------|\n",
+            "| `a`  | 1     |\n",
+        );
+
+        assert_eq!(
+            render(markdown),
+            concat!(
+                "1. first\n",
+                "2. second\n",
+                "  - [x] done\n",
+                "  - [ ] todo\n",
+                "\n",
+                "Name\tValue\n",
+                "a\t1",
+            )
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! Detailed rendering: the HTML plus metadata gathered from the same parse.
+
+use comrak::{
+    Options, format_html_with_plugins,
+    nodes::{AstNode, NodeValue},
+    options::Plugins,
+    parse_document,
+};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+use crate::ast::{self, Arena};
+use crate::mentions::{self, IssueReference};
+use crate::toc::{self, SlugHeadingAdapter, Slugger, TocEntry};
+use crate::{
+    RenderOptions, SYNTAX_SET, alerts, containers, csv, emoji, finish_html, footnotes,
+    front_matter, streaming, tables, tasks, urls, wikilinks,
+};
+
+/// A heading in document order.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// Heading level, 1 to 6.
+    pub level: u8,
+    /// Plain text content of the heading.
+    pub text: String,
+    /// GitHub-style slug, unique within the document. Used as the heading `id`
+    /// when [`RenderOptions::heading_ids`] is on.
+    pub slug: String,
+    /// 1-based source line.
+    pub line: usize,
+}
+
+/// A non-fatal problem found while rendering.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based source line the problem was found on.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Rendered HTML together with metadata extracted from the document.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct RenderResult {
+    pub(crate) html: String,
+    pub(crate) front_matter: Option<serde_json::Value>,
+    pub(crate) front_matter_raw: Option<String>,
+    pub(crate) headings: Vec<Heading>,
+    pub(crate) toc: Vec<TocEntry>,
+    pub(crate) languages: Vec<String>,
+    pub(crate) has_math: bool,
+    pub(crate) has_mermaid: bool,
+    pub(crate) has_iframes: bool,
+    pub(crate) warnings: Vec<Diagnostic>,
+    pub(crate) wikilinks: Vec<String>,
+    pub(crate) mentions: Vec<String>,
+    pub(crate) issue_references: Vec<IssueReference>,
+}
+
+#[wasm_bindgen]
+impl RenderResult {
+    #[wasm_bindgen(getter)]
+    pub fn html(&self) -> String {
+        self.html.clone()
+    }
+
+    /// The front matter parsed from YAML (`---`) or TOML (`+++`) into a plain
+    /// object, or `undefined` if the document has none or it failed to parse.
+    #[wasm_bindgen(getter)]
+    pub fn front_matter(&self) -> JsValue {
+        match &self.front_matter {
+            Some(value) => value
+                .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+                .unwrap_or(JsValue::UNDEFINED),
+            None => JsValue::UNDEFINED,
+        }
+    }
+
+    /// The front matter block without its delimiters, if the document has one.
+    #[wasm_bindgen(getter)]
+    pub fn front_matter_raw(&self) -> Option<String> {
+        self.front_matter_raw.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn headings(&self) -> Vec<Heading> {
+        self.headings.clone()
+    }
+
+    /// The headings nested into a tree of `{ level, text, slug, children }` objects.
+    #[wasm_bindgen(getter)]
+    pub fn toc(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.toc).unwrap_or(JsValue::UNDEFINED)
+    }
+
+    /// Code block languages in order of first use.
+    #[wasm_bindgen(getter)]
+    pub fn languages(&self) -> Vec<String> {
+        self.languages.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn has_math(&self) -> bool {
+        self.has_math
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn has_mermaid(&self) -> bool {
+        self.has_mermaid
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn has_iframes(&self) -> bool {
+        self.has_iframes
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn warnings(&self) -> Vec<Diagnostic> {
+        self.warnings.clone()
+    }
+
+    /// Page names referenced by wikilinks, in order of first use, including
+    /// pages that didn't resolve.
+    #[wasm_bindgen(getter)]
+    pub fn wikilinks(&self) -> Vec<String> {
+        self.wikilinks.clone()
+    }
+
+    /// Usernames of the linked `@mentions`, in order of first use.
+    #[wasm_bindgen(getter)]
+    pub fn mentions(&self) -> Vec<String> {
+        self.mentions.clone()
+    }
+
+    /// The linked `#123` and `org/repo#123` references, in order of first use.
+    #[wasm_bindgen(getter)]
+    pub fn issue_references(&self) -> Vec<IssueReference> {
+        self.issue_references.clone()
+    }
+}
+
+/// Parse `markdown` once, collect its metadata and render it to HTML.
+pub(crate) fn render_document(
+    markdown: &str,
+    options: &RenderOptions,
+    plugins: &Plugins,
+) -> RenderResult {
+    let markdown = match options.streaming {
+        true => streaming::close_unfinished(markdown, options.math_dollars),
+        false => Cow::Borrowed(markdown),
+    };
+    let comrak_options = comrak_options_for(&markdown, options);
+    let arena = Arena::new();
+    let root = parse(&arena, &markdown, &comrak_options, options);
+
+    let mut result = prepare(&arena, root, options);
+    let mut headings = result.headings.iter().collect::<Vec<_>>();
+
+    if let Some(tail) = streaming::tail_block(root).filter(|_| options.streaming) {
+        let sourcepos = tail.data.borrow().sourcepos;
+        let (tail_headings, rest) = headings.into_iter().partition::<Vec<_>, _>(|h| {
+            (sourcepos.start.line..=sourcepos.end.line).contains(&h.line)
+        });
+        headings = rest;
+
+        let tail_slugs = options
+            .heading_ids
+            .then(|| tail_headings.iter().map(|h| h.slug.clone()).collect());
+        let html = format_raw(tail, &comrak_options, plugins, tail_slugs);
+        let marked = arena.alloc(NodeValue::Raw(streaming::mark_streaming(&html)).into());
+        marked.data.borrow_mut().sourcepos = sourcepos;
+        tail.insert_before(marked);
+        tail.detach();
+    }
+
+    let heading_slugs = options
+        .heading_ids
+        .then(|| headings.iter().map(|h| h.slug.clone()).collect());
+    result.html = format_node(root, &comrak_options, plugins, heading_slugs);
+
+    result
+}
+
+/// The comrak options for `markdown`, switched to the TOML front matter
+/// delimiter when the document opens with one.
+pub(crate) fn comrak_options_for(markdown: &str, options: &RenderOptions) -> Rc<Options<'static>> {
+    let comrak_options = options.comrak_options();
+    if !(options.front_matter && front_matter::starts_with_toml(markdown)) {
+        return comrak_options;
+    }
+
+    let mut toml_options = (*comrak_options).clone();
+    toml_options.extension.front_matter_delimiter = Some(front_matter::TOML_DELIMITER.into());
+    Rc::new(toml_options)
+}
+
+/// Parse `markdown`, adding the `:::` containers and the table features comrak
+/// doesn't know about when they are on in `options`.
+pub(crate) fn parse<'a>(
+    arena: &'a Arena<'a>,
+    markdown: &str,
+    comrak_options: &Options,
+    options: &RenderOptions,
+) -> &'a AstNode<'a> {
+    let (markdown, containers) = match options.containers {
+        true => containers::strip_fences(markdown),
+        false => (Cow::Borrowed(markdown), Vec::new()),
+    };
+
+    let root = parse_document(arena, &markdown, comrak_options);
+    containers::wrap_containers(arena, root, &containers, options);
+    // Table spans need the source, so tables are rendered here.
+    if tables::customizes_tables(options) {
+        tables::render_tables(arena, root, &markdown, options);
+    }
+    root
+}
+
+/// Collect the document metadata and apply the AST transforms enabled in `options`.
+pub(crate) fn prepare<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+) -> RenderResult {
+    let mut result = collect_metadata(root);
+    transform(arena, root, options, &mut result, 0);
+    result
+}
+
+/// Apply the AST transforms enabled in `options`, adding what they find to
+/// `result`. Table of contents placeholders get `result.toc`, and task indices
+/// are counted from `first_task`.
+pub(crate) fn transform<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+    result: &mut RenderResult,
+    first_task: usize,
+) {
+    if tasks::renders_tasks(options) {
+        tasks::render_tasks(arena, root, options, first_task);
+    }
+    if alerts::customizes_alerts(options) {
+        alerts::render_alerts(arena, root, options);
+    }
+    if options.csv_tables {
+        let warnings = csv::render_csv_tables(arena, root, options);
+        result.warnings.extend(warnings);
+    }
+    if options.toc_placeholder {
+        toc::replace_placeholders(arena, root, &result.toc);
+    }
+    if options.wikilinks {
+        result.wikilinks = wikilinks::resolve_wikilinks(arena, root, options);
+    }
+    if mentions::links_references(options) {
+        let references = mentions::link_references(arena, root, options);
+        result.mentions = references.mentions;
+        result.issue_references = references.issues;
+    }
+    if options.emoji {
+        emoji::replace_shortcodes(arena, root, options);
+    }
+    if urls::rewrites_urls(options) {
+        urls::rewrite_urls(root, options);
+    }
+    if options.footnotes {
+        footnotes::add_previews(arena, root);
+    }
+}
+
+/// Render `node` and its descendants to finished HTML.
+///
+/// `heading_slugs` are the ids for the headings inside `node`, in document
+/// order, when heading ids are enabled.
+pub(crate) fn format_node<'a>(
+    node: &'a AstNode<'a>,
+    comrak_options: &Options,
+    plugins: &Plugins,
+    heading_slugs: Option<Vec<String>>,
+) -> String {
+    finish_html(&format_raw(node, comrak_options, plugins, heading_slugs))
+}
+
+/// Render `node` to HTML as comrak writes it, before code block wrappers are
+/// closed and the output is sanitized.
+fn format_raw<'a>(
+    node: &'a AstNode<'a>,
+    comrak_options: &Options,
+    plugins: &Plugins,
+    heading_slugs: Option<Vec<String>>,
+) -> String {
+    let heading_adapter;
+    let mut plugins = plugins.clone();
+    if let Some(slugs) = heading_slugs {
+        heading_adapter = SlugHeadingAdapter::new(slugs);
+        plugins.render.heading_adapter = Some(&heading_adapter);
+    }
+
+    let mut html = String::new();
+    format_html_with_plugins(node, comrak_options, &mut html, &plugins).unwrap();
+    html
+}
+
+/// Collect the front matter, headings, code languages and warnings of the
+/// document, before any transform.
+pub(crate) fn collect_metadata<'a>(root: &'a AstNode<'a>) -> RenderResult {
+    let mut result = RenderResult::default();
+    let mut previous_level = 0;
+    let mut slugger = Slugger::default();
+
+    for node in root.descendants() {
+        let ast = node.data.borrow();
+        let line = ast.sourcepos.start.line;
+
+        match ast.value {
+            NodeValue::FrontMatter(ref raw) => {
+                result.front_matter_raw = Some(front_matter::strip_delimiters(raw).into());
+                match front_matter::parse(raw) {
+                    Ok(value) => result.front_matter = Some(value),
+                    Err(message) => result.warnings.push(Diagnostic { line, message }),
+                }
+            }
+            NodeValue::Heading(ref heading) => {
+                if previous_level > 0 && heading.level > previous_level + 1 {
+                    result.warnings.push(Diagnostic {
+                        line,
+                        message: format!(
+                            "heading level jumps from h{} to h{}",
+                            previous_level, heading.level
+                        ),
+                    });
+                }
+                previous_level = heading.level;
+
+                let text = ast::text_content(node);
+                result.headings.push(Heading {
+                    level: heading.level,
+                    slug: slugger.slug(&text),
+                    text,
+                    line,
+                });
+            }
+            NodeValue::CodeBlock(ref block) => {
+                let Some(lang) = ast::code_block_lang(&block.info) else {
+                    continue;
+                };
+
+                match lang {
+                    "math" => result.has_math = true,
+                    "mermaid" => result.has_mermaid = true,
+                    _ if SYNTAX_SET.find_syntax_by_token(lang).is_none() => {
+                        result.warnings.push(Diagnostic {
+                            line,
+                            message: format!("no syntax highlighting for language `{}`", lang),
+                        });
+                    }
+                    _ => {}
+                }
+
+                if !result.languages.iter().any(|l| l == lang) {
+                    result.languages.push(lang.into());
+                }
+            }
+            NodeValue::Math(..) => result.has_math = true,
+            NodeValue::HtmlBlock(ref block) if contains_iframe(&block.literal) => {
+                result.has_iframes = true;
+            }
+            NodeValue::HtmlInline(ref html) if contains_iframe(html) => {
+                result.has_iframes = true;
+            }
+            _ => {}
+        }
+    }
+
+    result.toc = toc::build_toc(&result.headings);
+    result
+}
+
+fn contains_iframe(html: &str) -> bool {
+    html.to_ascii_lowercase().contains("<iframe")
+}
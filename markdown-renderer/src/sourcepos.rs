@@ -0,0 +1,110 @@
+//! Mapping source lines to rendered block elements for editor scroll sync.
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// The `data-sourcepos` attribute in the opening tags of a raw wrapper node.
+static SOURCEPOS_ATTR_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"data-sourcepos="([^"]*)""#).unwrap());
+
+/// Find the innermost block containing the 1-based source `line` that is
+/// rendered as an element carrying `data-sourcepos`, and return that value.
+///
+/// Raw wrappers, such as `:::` containers, are searched too; they carry the
+/// `data-sourcepos` they were written with. Children are not assumed to be in
+/// source order, as comrak moves footnote definitions to the end.
+pub(crate) fn block_at_line<'a>(root: &'a AstNode<'a>, line: usize) -> Option<String> {
+    let mut found = None;
+    let mut parent = root;
+
+    'descend: loop {
+        for child in parent.children() {
+            let sourcepos = child.data.borrow().sourcepos;
+            if sourcepos.start.line > line || sourcepos.end.line < line {
+                continue;
+            }
+
+            match child.data.borrow().value {
+                NodeValue::Raw(ref raw) => {
+                    if let Some(captures) = SOURCEPOS_ATTR_REGEX.captures(raw) {
+                        found = Some(captures[1].to_string());
+                    }
+                }
+                _ if renders_element(child) => found = Some(sourcepos.to_string()),
+                _ => continue,
+            }
+            parent = child;
+            continue 'descend;
+        }
+
+        break;
+    }
+
+    found
+}
+
+/// Whether comrak writes an element with `data-sourcepos` for this block.
+fn renders_element<'a>(node: &'a AstNode<'a>) -> bool {
+    match node.data.borrow().value {
+        NodeValue::Paragraph => !in_tight_list(node),
+        NodeValue::FrontMatter(..) | NodeValue::HtmlBlock(..) => false,
+        ref value => value.block(),
+    }
+}
+
+/// Paragraphs directly inside items of a tight list are rendered without `<p>`.
+fn in_tight_list<'a>(paragraph: &'a AstNode<'a>) -> bool {
+    let Some(list) = paragraph.parent().and_then(|item| item.parent()) else {
+        return false;
+    };
+
+    matches!(list.data.borrow().value, NodeValue::List(ref list) if list.tight)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{Arena, Options, parse_document};
+
+    fn block_id(markdown: &str, line: usize) -> Option<String> {
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &Options::default());
+        block_at_line(root, line)
+    }
+
+    #[test]
+    fn test_block_at_line() {
+        let markdown = concat!(
+            "# Title\n",
+            "\n",
+            "> quoted\n",
+            "> text\n",
+            "\n",
+            "- tight\n",
+            "- list\n",
+            "\n",
+            "```\n",
+            "code\n",
+            "```\n",
+        );
+
+        assert_eq!(block_id(markdown, 1).as_deref(), Some("1:1-1:7"));
+        assert_eq!(block_id(markdown, 2), None);
+        assert_eq!(block_id(markdown, 4).as_deref(), Some("3:3-4:6"));
+        assert_eq!(block_id(markdown, 7).as_deref(), Some("7:1-7:6"));
+        assert_eq!(block_id(markdown, 10).as_deref(), Some("9:1-11:3"));
+        assert_eq!(block_id(markdown, 99), None);
+    }
+
+    #[test]
+    fn test_block_at_line_after_footnote_definition() {
+        let mut options = Options::default();
+        options.extension.footnotes = true;
+        let arena = Arena::new();
+        let root = parse_document(&arena, "Text[^1]\n\n[^1]: Note.\n\nAfter\n", &options);
+
+        assert_eq!(block_at_line(root, 3).as_deref(), Some("3:7-3:11"));
+        assert_eq!(block_at_line(root, 5).as_deref(), Some("5:1-5:5"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Document statistics for editor status bars.
+
+use comrak::nodes::{AstNode, NodeValue};
+use wasm_bindgen::prelude::*;
+
+use crate::RenderOptions;
+use crate::ast;
+
+/// Reading speed for space-separated text, in words per minute.
+const WORDS_PER_MINUTE: usize = 200;
+/// Reading speed for CJK text, in characters per minute.
+const CJK_CHARS_PER_MINUTE: usize = 500;
+
+/// Counts gathered from a parsed document by [`crate::document_stats`].
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    /// Words of prose, outside code and math. Each CJK character counts as a word.
+    pub words: usize,
+    /// Estimated reading time of the prose, rounded up to whole minutes.
+    pub reading_time_minutes: usize,
+    pub headings: usize,
+    /// Code blocks, not counting ```` ```math ```` fences.
+    pub code_blocks: usize,
+    /// Code blocks per info string language, in order of first use.
+    pub code_languages: Vec<LanguageCount>,
+    /// Inline and display math expressions, ```` ```math ```` fences included.
+    pub math: usize,
+    pub images: usize,
+    pub links: usize,
+    pub tasks_done: usize,
+    pub tasks_total: usize,
+    /// Tasks per extended state, such as `cancelled` for `[-]`, in order of
+    /// first use. Counted in `tasks_total` but not `tasks_done`.
+    pub task_states: Vec<TaskStateCount>,
+}
+
+/// Number of code blocks in one language.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageCount {
+    pub language: String,
+    pub count: usize,
+}
+
+/// Number of tasks in one extended state.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskStateCount {
+    pub state: String,
+    pub count: usize,
+}
+
+/// Collect the statistics of the document rooted at `root`.
+pub(crate) fn document_stats<'a>(root: &'a AstNode<'a>, options: &RenderOptions) -> DocumentStats {
+    let mut stats = DocumentStats::default();
+    let mut prose = String::new();
+
+    for node in root.descendants() {
+        match node.data.borrow().value {
+            NodeValue::Text(ref text) if !in_image(node) => prose.push_str(text),
+            NodeValue::SoftBreak | NodeValue::LineBreak => prose.push(' '),
+            NodeValue::Heading(..) => {
+                stats.headings += 1;
+                prose.push(' ');
+            }
+            NodeValue::CodeBlock(ref block) => match ast::code_block_lang(&block.info) {
+                Some("math") => stats.math += 1,
+                lang => {
+                    stats.code_blocks += 1;
+                    if let Some(lang) = lang {
+                        add_language(&mut stats.code_languages, lang);
+                    }
+                }
+            },
+            NodeValue::Math(..) => stats.math += 1,
+            NodeValue::Image(..) => stats.images += 1,
+            NodeValue::Link(..) | NodeValue::WikiLink(..) => stats.links += 1,
+            NodeValue::TaskItem(ref task) => match task.symbol {
+                None => stats.tasks_total += 1,
+                Some('x' | 'X') => {
+                    stats.tasks_total += 1;
+                    stats.tasks_done += 1;
+                }
+                Some(symbol) => {
+                    if let Some(state) = options.task_state(symbol) {
+                        stats.tasks_total += 1;
+                        add_state(&mut stats.task_states, state);
+                    }
+                }
+            },
+            ref value if value.block() => prose.push(' '),
+            _ => {}
+        }
+    }
+
+    let (words, cjk_chars) = count_words(&prose);
+    stats.words = words;
+    stats.reading_time_minutes =
+        (words - cjk_chars).div_ceil(WORDS_PER_MINUTE) + cjk_chars.div_ceil(CJK_CHARS_PER_MINUTE);
+    stats
+}
+
+fn add_language(counts: &mut Vec<LanguageCount>, language: &str) {
+    match counts.iter_mut().find(|count| count.language == language) {
+        Some(count) => count.count += 1,
+        None => counts.push(LanguageCount {
+            language: language.into(),
+            count: 1,
+        }),
+    }
+}
+
+fn add_state(counts: &mut Vec<TaskStateCount>, state: &str) {
+    match counts.iter_mut().find(|count| count.state == state) {
+        Some(count) => count.count += 1,
+        None => counts.push(TaskStateCount {
+            state: state.into(),
+            count: 1,
+        }),
+    }
+}
+
+fn in_image<'a>(node: &'a AstNode<'a>) -> bool {
+    node.ancestors()
+        .any(|ancestor| matches!(ancestor.data.borrow().value, NodeValue::Image(..)))
+}
+
+/// Count the words in `text` and how many of them are CJK characters.
+///
+/// Runs of letters and digits are words, joined across apostrophes and
+/// hyphens. CJK scripts don't separate words with spaces, so every Han,
+/// Hiragana or Katakana character counts as a word.
+fn count_words(text: &str) -> (usize, usize) {
+    let mut words = 0;
+    let mut cjk_chars = 0;
+    let mut in_word = false;
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            words += 1;
+            cjk_chars += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+                in_word = true;
+            }
+        } else if !(in_word && matches!(c, '\'' | '’' | '-')) {
+            in_word = false;
+        }
+    }
+
+    (words, cjk_chars)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FFFF}'
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{Arena, parse_document};
+
+    use crate::OPTIONS;
+
+    #[test]
+    fn test_count_words() {
+        assert_eq!(count_words("Hello, world!"), (2, 0));
+        assert_eq!(count_words("don't over-think it"), (3, 0));
+        assert_eq!(count_words("Rust は速い"), (4, 3));
+        assert_eq!(count_words("中文字数统计"), (6, 6));
+        assert_eq!(count_words("  "), (0, 0));
+    }
+
+    #[test]
+    fn test_document_stats() {
+        let markdown = concat!(
+            "# Title\n",
+            "\n",
+            "Some *prose* with a [link](https://example.com) and ![alt text](a.png).\n",
+            "Math $x$ and `code`.\n",
+            "\n",
+            "- [x] done\n",
+            "- [ ] todo\n",
+            "\n",
+            "```rust\n",
+            "fn main() {}\n",
+            "```\n",
+            "\n",
+            "```rust\n",
+            "```\n",
+            "\n",
+            "```math\n",
+            "E = mc^2\n",
+            "```\n",
+        );
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &OPTIONS);
+        let stats = document_stats(root, &RenderOptions::new());
+
+        assert_eq!(stats.words, 11);
+        assert_eq!(stats.reading_time_minutes, 1);
+        assert_eq!(stats.headings, 1);
+        assert_eq!(stats.code_blocks, 2);
+        assert_eq!(
+            stats.code_languages,
+            vec![LanguageCount {
+                language: "rust".into(),
+                count: 2
+            }]
+        );
+        assert_eq!(stats.math, 2);
+        assert_eq!(stats.images, 1);
+        assert_eq!(stats.links, 1);
+        assert_eq!((stats.tasks_done, stats.tasks_total), (1, 2));
+    }
+
+    #[test]
+    fn test_task_states() {
+        let mut options = RenderOptions::new();
+        options.extended_tasks = true;
+        options.set_task_state('?', None);
+
+        let markdown = "- [x] done\n- [-] dropped\n- [~] doing\n- [-] dropped\n- [?] unknown\n";
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &options.comrak_options());
+        let stats = document_stats(root, &options);
+
+        assert_eq!((stats.tasks_done, stats.tasks_total), (1, 4));
+        assert_eq!(
+            stats.task_states,
+            vec![
+                TaskStateCount {
+                    state: "cancelled".into(),
+                    count: 2
+                },
+                TaskStateCount {
+                    state: "in-progress".into(),
+                    count: 1
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,624 @@
+//! Streaming-safe rendering of partial markdown, e.g. LLM output arriving
+//! token by token.
+//!
+//! Unfinished constructs at the end of the input are closed before parsing so
+//! the partial document renders the way it will once complete, instead of an
+//! open fence swallowing the rest of the message or raw `**` flashing up.
+//!
+//! [`StreamRenderer`] builds on this for append-only input: blocks are
+//! rendered once when they are finished and never touched again, so each
+//! chunk only costs the text still being written.
+
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+use wasm_bindgen::prelude::*;
+
+use crate::ast::Arena;
+use crate::render;
+use crate::{PLUGINS, RenderOptions, Themes};
+
+/// Block quote and list item markers at the start of a line. The indentation
+/// after them is left for the code fence checks.
+static CONTAINER_PREFIX_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?: {0,3}(?:>[ \t]?|(?:[-+*]|\d{1,9}[.)]) ))*").unwrap());
+
+/// Close the constructs left open at the end of `markdown`: code fences,
+/// code spans, inline math, emphasis, link destinations and table rows.
+///
+/// Openers with nothing after them yet are dropped rather than closed, so
+/// `**` alone does not turn into a thematic break.
+pub(crate) fn close_unfinished(markdown: &str, math_dollars: bool) -> Cow<'_, str> {
+    // The open fence and the container markers before it.
+    let mut fence: Option<(char, usize, &str)> = None;
+    let mut tail_start = 0;
+    let mut offset = 0;
+
+    for line in markdown.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let prefix = CONTAINER_PREFIX_REGEX
+            .find(content)
+            .map_or("", |m| m.as_str());
+
+        match fence {
+            Some((c, len, fence_prefix)) => {
+                // Lines of a fence in a quote or list item carry its markers
+                // or indentation.
+                let unprefixed = match fence_prefix.is_empty() {
+                    true => content,
+                    false => &content[prefix.len()..],
+                };
+                if is_closing_fence(unprefixed, c, len) {
+                    fence = None;
+                    tail_start = offset + line.len();
+                }
+            }
+            None => {
+                if let Some((c, len)) = opening_fence(&content[prefix.len()..]) {
+                    fence = Some((c, len, prefix));
+                    tail_start = offset;
+                } else if content.trim().is_empty() {
+                    tail_start = offset + line.len();
+                }
+            }
+        }
+
+        offset += line.len();
+    }
+
+    if let Some((c, len, prefix)) = fence {
+        let mut closed = markdown.to_string();
+        if !closed.ends_with('\n') {
+            closed.push('\n');
+        }
+        // Quote markers continue the quote; list markers become the
+        // indentation of the item's content.
+        closed.extend(prefix.chars().map(|ch| if ch == '>' { ch } else { ' ' }));
+        closed.extend(std::iter::repeat_n(c, len));
+        closed.push('\n');
+        return Cow::Owned(closed);
+    }
+
+    let tail = &markdown[tail_start..];
+    let closed_tail = close_table_row(&close_inline(tail, math_dollars));
+    if closed_tail == tail {
+        return Cow::Borrowed(markdown);
+    }
+
+    Cow::Owned(format!("{}{}", &markdown[..tail_start], closed_tail))
+}
+
+/// The fence character and length if `line` opens a fenced code block.
+pub(crate) fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let c = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|ch| *ch == c).count();
+    if len < 3 || (c == '`' && trimmed[len..].contains('`')) {
+        return None;
+    }
+
+    Some((c, len))
+}
+
+/// Whether `line` closes a fenced code block opened with `len` of `c`.
+pub(crate) fn is_closing_fence(line: &str, c: char, len: usize) -> bool {
+    let trimmed = line.trim();
+    trimmed.len() >= len && trimmed.chars().all(|ch| ch == c)
+}
+
+/// An inline construct that was opened but not closed.
+struct Open {
+    /// Byte offset of the opener in the text.
+    start: usize,
+    /// Byte offset just past the opener.
+    end: usize,
+    /// What to append to close it. `None` for brackets, which stay literal.
+    closer: Option<String>,
+}
+
+/// Append closers for the inline constructs left open in the last block.
+fn close_inline(text: &str, math_dollars: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut stack: Vec<Open> = Vec::new();
+    let mut i = 0;
+
+    'scan: while i < bytes.len() {
+        let c = bytes[i];
+        let run = bytes[i..].iter().take_while(|b| **b == c).count();
+
+        match c {
+            b'\\' => {
+                i += 2;
+                continue;
+            }
+            b'`' => match find_run(bytes, i + run, b'`', run) {
+                Some(close) => i = close + run,
+                None => {
+                    stack.push(open(i, run, Some(text[i..i + run].into())));
+                    break 'scan;
+                }
+            },
+            b'$' if math_dollars && run <= 2 => {
+                let next = bytes.get(i + run);
+                if run == 1 && next.is_none_or(|b| b.is_ascii_digit() || b.is_ascii_whitespace()) {
+                    i += run;
+                    continue;
+                }
+
+                match find_run(bytes, i + run, b'$', run) {
+                    Some(close) => i = close + run,
+                    None => {
+                        stack.push(open(i, run, Some(text[i..i + run].into())));
+                        break 'scan;
+                    }
+                }
+            }
+            b'*' | b'_' | b'~' => {
+                let before = text[..i].chars().next_back();
+                let after = text[i + run..].chars().next();
+                let left_flanking = after.is_some_and(|ch| !ch.is_whitespace());
+                let right_flanking = before.is_some_and(|ch| !ch.is_whitespace());
+                let intraword = c == b'_'
+                    && before.is_some_and(char::is_alphanumeric)
+                    && after.is_some_and(char::is_alphanumeric);
+
+                if intraword || (c == b'~' && run < 2) {
+                    i += run;
+                    continue;
+                }
+
+                let closes_top = stack.last().is_some_and(|top| {
+                    top.closer.as_deref() == Some(&text[i..i + run])
+                        && text[top.start..].starts_with(&text[i..i + run])
+                });
+                if right_flanking && closes_top {
+                    stack.pop();
+                } else if left_flanking || text[i + run..].trim().is_empty() {
+                    // A run at the end is an opener still waiting for its text.
+                    stack.push(open(i, run, Some(text[i..i + run].into())));
+                }
+            }
+            b'[' => stack.push(open(i, 1, None)),
+            b']' => {
+                if let Some(index) = stack.iter().rposition(|o| o.closer.is_none()) {
+                    stack.truncate(index);
+                }
+
+                if bytes.get(i + 1) == Some(&b'(') {
+                    match text[i + 2..].find(')') {
+                        Some(close) => i += 2 + close,
+                        None => {
+                            stack.push(open(i + 1, 1, Some(")".into())));
+                            break 'scan;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        i += run;
+    }
+
+    let mut end = text.trim_end().len();
+    let mut closers = String::new();
+
+    while let Some(open) = stack.pop() {
+        let Some(closer) = open.closer else {
+            continue;
+        };
+
+        if text[open.end..end].trim().is_empty() && closers.is_empty() {
+            end = text[..open.start].trim_end().len();
+        } else {
+            closers.push_str(&closer);
+        }
+    }
+
+    let trailing = &text[text.trim_end().len()..];
+    format!("{}{}{}", &text[..end], closers, trailing)
+}
+
+fn open(start: usize, len: usize, closer: Option<String>) -> Open {
+    Open {
+        start,
+        end: start + len,
+        closer,
+    }
+}
+
+/// Find the next run of exactly `len` `c` bytes at or after `from`.
+fn find_run(bytes: &[u8], from: usize, c: u8, len: usize) -> Option<usize> {
+    let mut i = from;
+    while i < bytes.len() {
+        if bytes[i] != c {
+            i += if bytes[i] == b'\\' { 2 } else { 1 };
+            continue;
+        }
+
+        let run = bytes[i..].iter().take_while(|b| **b == c).count();
+        if run == len {
+            return Some(i);
+        }
+        i += run;
+    }
+
+    None
+}
+
+/// Complete the last row of a table being typed: finish a partial delimiter
+/// row, add a delimiter row under a lone header row, and pad short body rows.
+fn close_table_row(text: &str) -> String {
+    let content = text.trim_end();
+    let lines = content.lines().collect::<Vec<&str>>();
+    let Some(last) = lines
+        .last()
+        .filter(|line| line.trim_start().starts_with('|'))
+    else {
+        return text.into();
+    };
+    let header_cells = count_cells(lines[0]);
+
+    let completed = match lines.len() {
+        1 if last.trim_end().ends_with('|') => {
+            format!("{}\n{}", content, delimiter_row(header_cells))
+        }
+        2 if is_delimiter_row(last) => {
+            let head = &content[..content.len() - last.len()];
+            format!("{}{}", head, delimiter_row(header_cells))
+        }
+        n if n > 2 && is_delimiter_row(lines[1]) => {
+            let missing = header_cells.saturating_sub(count_cells(last));
+            let mut row = last.trim_end().trim_end_matches('|').to_string();
+            for _ in 0..missing {
+                row.push_str(" | ");
+            }
+            row.push_str(" |");
+            format!("{}{}", &content[..content.len() - last.len()], row)
+        }
+        _ => return text.into(),
+    };
+
+    format!("{}{}", completed, &text[content.len()..])
+}
+
+fn count_cells(row: &str) -> usize {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = row.strip_suffix('|').unwrap_or(row);
+
+    let mut cells = 1;
+    let mut escaped = false;
+    for c in row.chars() {
+        match c {
+            '\\' => escaped = !escaped,
+            '|' if !escaped => cells += 1,
+            _ => escaped = false,
+        }
+    }
+
+    cells
+}
+
+fn is_delimiter_row(row: &str) -> bool {
+    let row = row.trim();
+    row.contains('-') && row.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+fn delimiter_row(cells: usize) -> String {
+    let mut row = String::from("|");
+    for _ in 0..cells {
+        row.push_str(" --- |");
+    }
+    row
+}
+
+/// The last top-level block, which is still being written.
+pub(crate) fn tail_block<'a>(root: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
+    root.reverse_children().find(|node| {
+        !matches!(
+            node.data.borrow().value,
+            NodeValue::FootnoteDefinition(..) | NodeValue::FrontMatter(..)
+        )
+    })
+}
+
+/// Add `data-streaming="true"` to the first element of `html`.
+pub(crate) fn mark_streaming(html: &str) -> String {
+    const ATTRIBUTE: &str = " data-streaming=\"true\"";
+
+    let tag_end = html.starts_with('<').then(|| html.find('>')).flatten();
+    let Some(tag_end) = tag_end else {
+        return format!("<div{}>{}</div>", ATTRIBUTE, html);
+    };
+
+    let insert_at = if html[..tag_end].ends_with('/') {
+        html[..tag_end - 1].trim_end().len()
+    } else {
+        tag_end
+    };
+    format!("{}{}{}", &html[..insert_at], ATTRIBUTE, &html[insert_at..])
+}
+
+/// The HTML produced by one [`StreamRenderer::push`].
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamUpdate {
+    /// HTML for the blocks finished by this chunk, to be appended after the
+    /// previously committed HTML. It never changes again.
+    pub committed: String,
+    /// HTML for the block still being written, replacing the previous preview.
+    pub preview: String,
+}
+
+/// Append-only renderer for markdown that arrives in chunks.
+///
+/// A top-level block is committed once the next block has started. Committed
+/// blocks are rendered exactly once; only the open tail is re-rendered on each
+/// chunk. Link reference definitions and footnotes only resolve within the
+/// text committed together with them.
+///
+/// Each commit is rendered as a document of its own: heading ids are only
+/// deduplicated, and footnotes and source lines only numbered, within it.
+/// [`RenderOptions::interactive_tasks`] is not supported, as toggling a task
+/// needs its line and index in the whole document; tasks are rendered as
+/// plain checkboxes.
+#[wasm_bindgen]
+pub struct StreamRenderer {
+    theme: &'static str,
+    options: RenderOptions,
+    pending: String,
+}
+
+#[wasm_bindgen]
+impl StreamRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(theme: Themes, options: &RenderOptions) -> Self {
+        let mut options = options.clone();
+        options.streaming = false;
+        options.interactive_tasks = false;
+
+        Self {
+            theme: theme.to_str(),
+            options,
+            pending: String::new(),
+        }
+    }
+
+    /// Append `chunk` to the stream.
+    pub fn push(&mut self, chunk: &str) -> StreamUpdate {
+        self.pending.push_str(chunk);
+
+        let boundary = self.commit_boundary();
+        let committed = match boundary {
+            0 => String::new(),
+            _ => self.commit(boundary),
+        };
+
+        let preview = match self.pending.trim().is_empty() {
+            true => String::new(),
+            false => {
+                let mut options = self.options.clone();
+                options.streaming = true;
+                render::render_document(&self.pending, &options, &PLUGINS[self.theme]).html
+            }
+        };
+
+        StreamUpdate { committed, preview }
+    }
+
+    /// End the stream and return the HTML for the remaining open block.
+    pub fn finish(&mut self) -> String {
+        self.commit(self.pending.len())
+    }
+}
+
+impl StreamRenderer {
+    /// Byte offset in the pending text where the last top-level block starts.
+    /// Everything before it is finished.
+    fn commit_boundary(&self) -> usize {
+        let comrak_options = render::comrak_options_for(&self.pending, &self.options);
+        let arena = Arena::new();
+        let root = render::parse(&arena, &self.pending, &comrak_options, &self.options);
+
+        let last_line = root
+            .children()
+            .filter(|node| !matches!(node.data.borrow().value, NodeValue::FootnoteDefinition(..)))
+            .map(|node| node.data.borrow().sourcepos.start.line)
+            .max()
+            .unwrap_or(1);
+
+        self.pending
+            .split_inclusive('\n')
+            .take(last_line.saturating_sub(1))
+            .map(str::len)
+            .sum()
+    }
+
+    /// Render and drop the first `len` bytes of the pending text.
+    fn commit(&mut self, len: usize) -> String {
+        let html = match self.pending[..len].trim().is_empty() {
+            true => String::new(),
+            false => {
+                render::render_document(&self.pending[..len], &self.options, &PLUGINS[self.theme])
+                    .html
+            }
+        };
+
+        self.pending.drain(..len);
+        // Only the start of the stream can hold front matter.
+        self.options.front_matter = false;
+        html
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(markdown: &str) -> String {
+        close_unfinished(markdown, true).into_owned()
+    }
+
+    #[test]
+    fn test_complete_input_is_untouched() {
+        let markdown = "# Title\n\n**bold** and `code` and $x$.\n\n```rust\nfn main() {}\n```\n";
+        assert!(matches!(close_unfinished(markdown, true), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_closes_fences() {
+        assert_eq!(
+            close("Text\n\n```rust\nfn main"),
+            "Text\n\n```rust\nfn main\n```\n"
+        );
+        assert_eq!(close("~~~~\ncode\n"), "~~~~\ncode\n~~~~\n");
+        assert_eq!(close("```\n**not emphasis"), "```\n**not emphasis\n```\n");
+    }
+
+    #[test]
+    fn test_closes_fences_in_quotes_and_lists() {
+        assert_eq!(
+            close("> ```rust\n> fn main"),
+            "> ```rust\n> fn main\n> ```\n"
+        );
+        assert_eq!(close("- ```\n  code"), "- ```\n  code\n  ```\n");
+        assert_eq!(close("1. > ~~~\n   > x\n"), "1. > ~~~\n   > x\n   > ~~~\n");
+        assert_eq!(
+            close("> ```\n> x\n> ```\n\nSome **bold"),
+            "> ```\n> x\n> ```\n\nSome **bold**"
+        );
+        // A quote marker is content in a fence opened outside the quote.
+        assert_eq!(close("```\n> ```\n"), "```\n> ```\n```\n");
+    }
+
+    #[test]
+    fn test_closes_inline_constructs() {
+        assert_eq!(close("Some **bold"), "Some **bold**");
+        assert_eq!(close("Some *it and **bold"), "Some *it and **bold***");
+        assert_eq!(close("Some ~~gone"), "Some ~~gone~~");
+        assert_eq!(close("Run `cargo te"), "Run `cargo te`");
+        assert_eq!(close("Inline $x^"), "Inline $x^$");
+        assert_eq!(close("See [docs](https://exa"), "See [docs](https://exa)");
+        assert_eq!(
+            close("**See [docs](https://exa"),
+            "**See [docs](https://exa)**"
+        );
+        assert_eq!(
+            close("Earlier\n\nnew **bold\n"),
+            "Earlier\n\nnew **bold**\n"
+        );
+    }
+
+    #[test]
+    fn test_leaves_non_openers_alone() {
+        assert_eq!(close("snake_case_name"), "snake_case_name");
+        assert_eq!(close("costs $5 today"), "costs $5 today");
+        assert_eq!(close("* list item"), "* list item");
+        assert_eq!(close("a [bracket"), "a [bracket");
+    }
+
+    #[test]
+    fn test_drops_empty_openers() {
+        assert_eq!(close("Text **"), "Text");
+        assert_eq!(close("Text **bold** and *"), "Text **bold** and");
+        assert_eq!(close("`"), "");
+    }
+
+    #[test]
+    fn test_completes_table_rows() {
+        assert_eq!(close("| a | b |"), "| a | b |\n| --- | --- |");
+        assert_eq!(close("| a | b |\n|--"), "| a | b |\n| --- | --- |");
+        assert_eq!(
+            close("| a | b |\n|---|---|\n| 1"),
+            "| a | b |\n|---|---|\n| 1 |  |"
+        );
+    }
+
+    #[test]
+    fn test_mark_streaming() {
+        assert_eq!(
+            mark_streaming("<p>Text</p>\n"),
+            "<p data-streaming=\"true\">Text</p>\n"
+        );
+        assert_eq!(
+            mark_streaming("<hr />\n"),
+            "<hr data-streaming=\"true\" />\n"
+        );
+        assert_eq!(
+            mark_streaming("text"),
+            "<div data-streaming=\"true\">text</div>"
+        );
+    }
+
+    #[test]
+    fn test_stream_renderer_commits_finished_blocks() {
+        let mut renderer = StreamRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+
+        let update = renderer.push("# Tit");
+        assert_eq!(update.committed, "");
+        assert_eq!(update.preview, "<h1 data-streaming=\"true\">Tit</h1>\n");
+
+        let update = renderer.push("le\n\nSome **bo");
+        assert_eq!(update.committed, "<h1>Title</h1>\n");
+        assert_eq!(
+            update.preview,
+            "<p data-streaming=\"true\">Some <strong>bo</strong></p>\n"
+        );
+
+        let update = renderer.push("ld**\n\n```rust\n\nfn main");
+        assert_eq!(update.committed, "<p>Some <strong>bold</strong></p>\n");
+        assert!(
+            update.preview.contains("code-block-wrapper"),
+            "{}",
+            update.preview
+        );
+
+        let update = renderer.push("() {}\n```\n");
+        assert_eq!(update.committed, "");
+
+        assert!(renderer.finish().contains("code-block-wrapper"));
+        assert_eq!(renderer.finish(), "");
+    }
+
+    #[test]
+    #[cfg_attr(feature = "sanitize", ignore = "Sanitization modifies HTML output")]
+    fn test_stream_renderer_only_reads_front_matter_at_start() {
+        let mut renderer = StreamRenderer::new(Themes::OneHalfDark, &RenderOptions::new());
+
+        assert_eq!(
+            renderer.push("---\ntitle: Post\n---\n\nA\n\n").committed,
+            ""
+        );
+        assert_eq!(renderer.push("---\n").committed, "<p>A</p>\n");
+
+        let update = renderer.push("x: 1\n---\n\nB\n");
+        assert_eq!(update.committed, "<hr />\n<h2>x: 1</h2>\n");
+        assert_eq!(renderer.finish(), "<p>B</p>\n");
+    }
+
+    #[test]
+    fn test_stream_renderer_renders_plain_tasks() {
+        let mut options = RenderOptions::new();
+        options.interactive_tasks = true;
+        let mut renderer = StreamRenderer::new(Themes::OneHalfDark, &options);
+
+        let update = renderer.push("- [ ] one\n\nText\n\n- [ ] two\n");
+        assert!(
+            update.committed.contains("checkbox"),
+            "{}",
+            update.committed
+        );
+        assert!(
+            !update.committed.contains("data-task-index"),
+            "{}",
+            update.committed
+        );
+        assert!(!renderer.finish().contains("data-task-index"));
+    }
+}
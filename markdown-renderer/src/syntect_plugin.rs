@@ -219,10 +219,19 @@ impl<S: HighlightStrategy + Send + Sync> SyntaxHighlighterAdapter for SyntectAda
     fn write_pre_tag(
         &self,
         output: &mut dyn Write,
-        attributes: HashMap<&'static str, Cow<'_, str>>,
+        mut attributes: HashMap<&'static str, Cow<'_, str>>,
     ) -> Result<(), fmt::Error> {
+        // The wrapper is the outermost element of the code block, so it carries
+        // the source position instead of the <pre>
+        output.write_str(r#"<div class="code-block-wrapper""#)?;
+        if let Some(sourcepos) = attributes.remove("data-sourcepos") {
+            output.write_str(" data-sourcepos=\"")?;
+            output.write_str(&sourcepos)?;
+            output.write_str("\"")?;
+        }
+        output.write_str(">")?;
+
         output.write_str(concat!(
-            r#"<div class="code-block-wrapper">"#,
             r#"<div class="code-block-header">"#,
             r#"<span class="code-block-language">Code</span>"#,
             r#"</div>"#,
@@ -0,0 +1,378 @@
+//! Table rendering: scrollable wrappers, alignment classes, sort hints,
+//! pandoc-style captions and MultiMarkdown-style cell spans.
+
+use comrak::nodes::{AstNode, NodeValue, Sourcepos, TableAlignment};
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+
+/// A number, optionally signed, with a currency symbol, thousands separators,
+/// decimals or a percent sign.
+static NUMBER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[-+]?[$€£¥]?(\d{1,3}(,\d{3})+|\d+)(\.\d+)?%?$").unwrap());
+
+/// An ISO date with an optional time, or a `d/m/y` or `m/d/y` date.
+static DATE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2}([T ]\d{2}:\d{2}(:\d{2})?)?|\d{1,2}/\d{1,2}/(\d{2}|\d{4}))$")
+        .unwrap()
+});
+
+/// The start of a caption paragraph.
+const CAPTION_PREFIX: &str = "Table:";
+
+/// The content of a cell merged into the cell above it.
+const ROWSPAN_MARKER: &str = "^^";
+
+/// A cell of a table, with the cells merged into it.
+struct Cell<'a> {
+    node: &'a AstNode<'a>,
+    colspan: usize,
+    rowspan: usize,
+    /// Merged into a spanning cell, so not rendered.
+    merged: bool,
+}
+
+/// Whether `options` changes how tables are rendered.
+pub(crate) fn customizes_tables(options: &RenderOptions) -> bool {
+    options.table && (options.table_wrappers || options.table_captions || options.table_spans)
+}
+
+/// Render the tables in the document the way `options` asks: inside a
+/// `<div class="table-wrapper">` with alignment classes and sort hints, with a
+/// `<caption>` from a `Table: caption` paragraph next to the table, and with
+/// cells spanning the `||` after them and the `^^` cells below them.
+///
+/// `markdown` is the parsed source, which tells `||` apart from an empty cell.
+/// comrak has no markup for any of these, so each table, row and cell is
+/// replaced by a raw node holding its opening tags, with its content as
+/// children and a raw closing tag last.
+pub(crate) fn render_tables<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    markdown: &str,
+    options: &RenderOptions,
+) {
+    let lines = markdown.lines().collect::<Vec<_>>();
+    let tables = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Table(..)))
+        .collect::<Vec<_>>();
+
+    for table in tables {
+        let alignments = match table.data.borrow().value {
+            NodeValue::Table(ref table) => table.alignments.clone(),
+            _ => continue,
+        };
+
+        let rows = table.children().collect::<Vec<_>>();
+        let headers = rows.iter().map(|row| is_header(row)).collect::<Vec<_>>();
+        let mut cells = rows
+            .iter()
+            .map(|row| {
+                row.children()
+                    .map(|node| Cell {
+                        node,
+                        colspan: 1,
+                        rowspan: 1,
+                        merged: false,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        if options.table_spans {
+            for (row, row_cells) in rows.iter().zip(&mut cells) {
+                merge_columns(row, row_cells, &lines);
+            }
+            merge_rows(&mut cells, &headers);
+        }
+        let sort_types = match options.table_wrappers {
+            true => (0..alignments.len())
+                .map(|column| sort_type(&cells, &headers, column))
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut in_body = false;
+        for ((row, row_cells), header) in rows.into_iter().zip(cells).zip(headers) {
+            let mut open_html = String::new();
+            if !header && !in_body {
+                open_html.push_str("<tbody>\n");
+                in_body = true;
+            }
+            if header {
+                open_html.push_str("<thead>\n");
+            }
+            open_html.push_str(&format!("<tr{}>\n", sourcepos_attr(row, options)));
+            let close_html = match header {
+                true => "</tr>\n</thead>\n",
+                false => "</tr>\n",
+            };
+
+            for (column, cell) in row_cells.into_iter().enumerate() {
+                if cell.merged {
+                    cell.node.detach();
+                    continue;
+                }
+
+                let tag = if header { "th" } else { "td" };
+                let mut cell_html = format!("<{}{}", tag, sourcepos_attr(cell.node, options));
+                if let Some(align) = alignments.get(column).and_then(align_name) {
+                    match options.table_wrappers {
+                        true => cell_html.push_str(&format!(" class=\"align-{}\"", align)),
+                        false => cell_html.push_str(&format!(" align=\"{}\"", align)),
+                    }
+                }
+                if cell.colspan > 1 {
+                    cell_html.push_str(&format!(" colspan=\"{}\"", cell.colspan));
+                }
+                if cell.rowspan > 1 {
+                    cell_html.push_str(&format!(" rowspan=\"{}\"", cell.rowspan));
+                }
+                if let Some(sort_type) = sort_types.get(column).filter(|_| header) {
+                    cell_html.push_str(&format!(" data-sort-type=\"{}\"", sort_type));
+                }
+                cell_html.push('>');
+                wrap(arena, cell.node, cell_html, format!("</{}>\n", tag));
+            }
+            wrap(arena, row, open_html, close_html.into());
+        }
+
+        let caption = match options.table_captions {
+            true => take_caption(arena, table),
+            false => None,
+        };
+        if let Some(caption) = caption {
+            table.prepend(caption);
+        }
+
+        let mut open_html = format!("<table{}>\n", sourcepos_attr(table, options));
+        let mut close_html = match in_body {
+            true => String::from("</tbody>\n</table>\n"),
+            false => String::from("</table>\n"),
+        };
+        if options.table_wrappers {
+            open_html.insert_str(0, "<div class=\"table-wrapper\">\n");
+            close_html.push_str("</div>\n");
+        }
+        let wrapper = wrap(arena, table, open_html, close_html);
+
+        // The caption paragraph is part of the table block.
+        if let Some(caption) = caption {
+            let table = wrapper.data.borrow().sourcepos;
+            let caption = caption.data.borrow().sourcepos;
+            wrapper.data.borrow_mut().sourcepos = Sourcepos {
+                start: table.start.min(caption.start),
+                end: table.end.max(caption.end),
+            };
+        }
+    }
+}
+
+/// Replace `node` with a raw node holding `open_html`, with the children of
+/// `node` moved into it and a raw `close_html` after them.
+fn wrap<'a>(
+    arena: &'a Arena<'a>,
+    node: &'a AstNode<'a>,
+    open_html: String,
+    close_html: String,
+) -> &'a AstNode<'a> {
+    let wrapper = arena.alloc(NodeValue::Raw(open_html).into());
+    wrapper.data.borrow_mut().sourcepos = node.data.borrow().sourcepos;
+    node.insert_before(wrapper);
+    for child in node.children().collect::<Vec<_>>() {
+        wrapper.append(child);
+    }
+    wrapper.append(arena.alloc(NodeValue::Raw(close_html).into()));
+    node.detach();
+    wrapper
+}
+
+fn sourcepos_attr<'a>(node: &'a AstNode<'a>, options: &RenderOptions) -> String {
+    match options.sourcepos {
+        true => format!(" data-sourcepos=\"{}\"", node.data.borrow().sourcepos),
+        false => String::new(),
+    }
+}
+
+fn is_header<'a>(row: &'a AstNode<'a>) -> bool {
+    matches!(row.data.borrow().value, NodeValue::TableRow(true))
+}
+
+fn align_name(alignment: &TableAlignment) -> Option<&'static str> {
+    match alignment {
+        TableAlignment::Left => Some("left"),
+        TableAlignment::Center => Some("center"),
+        TableAlignment::Right => Some("right"),
+        TableAlignment::None => None,
+    }
+}
+
+/// Take the `Table: caption` paragraph right after `table`, or else right
+/// before it, as a raw `<caption>` node.
+fn take_caption<'a>(arena: &'a Arena<'a>, table: &'a AstNode<'a>) -> Option<&'a AstNode<'a>> {
+    let paragraph = [table.next_sibling(), table.previous_sibling()]
+        .into_iter()
+        .flatten()
+        .find(|node| {
+            matches!(node.data.borrow().value, NodeValue::Paragraph)
+                && node.first_child().is_some_and(|first| {
+                    matches!(
+                        first.data.borrow().value,
+                        NodeValue::Text(ref text) if text.starts_with(CAPTION_PREFIX)
+                    )
+                })
+        })?;
+
+    let first = paragraph.first_child()?;
+    let mut empty = false;
+    if let NodeValue::Text(ref mut text) = first.data.borrow_mut().value {
+        let caption = text[CAPTION_PREFIX.len()..].trim_start().to_string();
+        empty = caption.is_empty();
+        *text = caption.into();
+    }
+    if empty {
+        first.detach();
+    }
+
+    let caption = wrap(arena, paragraph, "<caption>".into(), "</caption>\n".into());
+    caption.detach();
+    Some(caption)
+}
+
+/// Merge each cell written as `||`, with nothing between the pipes, into the
+/// cell before it.
+fn merge_columns<'a>(row: &'a AstNode<'a>, cells: &mut [Cell<'a>], lines: &[&str]) {
+    let start = row.data.borrow().sourcepos.start;
+    let Some(line) = lines
+        .get(start.line - 1)
+        .and_then(|line| line.get(start.column - 1..))
+    else {
+        return;
+    };
+
+    let mut owner = 0;
+    for (column, source) in source_cells(line).into_iter().enumerate().take(cells.len()) {
+        if column > 0 && source.is_empty() {
+            cells[owner].colspan += 1;
+            cells[column].merged = true;
+        } else {
+            owner = column;
+        }
+    }
+}
+
+/// Merge each cell holding only `^^` into the cell above it, or the cell
+/// spanning down to it, within the header or the body.
+fn merge_rows(cells: &mut [Vec<Cell<'_>>], headers: &[bool]) {
+    for row in 1..cells.len() {
+        for column in 0..cells[row].len() {
+            let cell = &cells[row][column];
+            if cell.merged
+                || headers[row] != headers[row - 1]
+                || ast::text_content(cell.node).trim() != ROWSPAN_MARKER
+            {
+                continue;
+            }
+
+            let owner = (0..row)
+                .rev()
+                .find(|&above| cells[above].get(column).is_none_or(|cell| !cell.merged))
+                .filter(|&above| {
+                    cells[above].get(column).is_some_and(|cell| {
+                        above + cell.rowspan == row && cell.colspan == cells[row][column].colspan
+                    })
+                });
+            if let Some(owner) = owner {
+                cells[owner][column].rowspan += 1;
+                cells[row][column].merged = true;
+            }
+        }
+    }
+}
+
+/// The source of the cells in a table row, split on unescaped pipes.
+fn source_cells(line: &str) -> Vec<&str> {
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if c == '|' && !escaped {
+            cells.push(&line[start..i]);
+            start = i + 1;
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(&line[start..]);
+
+    // Leading and trailing pipes don't start or end a cell.
+    if line.trim_start().starts_with('|') {
+        cells.remove(0);
+    }
+    if line.trim_end().ends_with('|') {
+        cells.pop();
+    }
+
+    cells
+}
+
+/// How the body cells of `column` should be compared: `number` or `date` when
+/// every non-empty cell is one, `text` otherwise.
+fn sort_type(cells: &[Vec<Cell<'_>>], headers: &[bool], column: usize) -> &'static str {
+    let values = cells
+        .iter()
+        .zip(headers)
+        .filter(|(_, header)| !**header)
+        .filter_map(|(row, _)| row.get(column))
+        .filter(|cell| !cell.merged)
+        .map(|cell| ast::text_content(cell.node).trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+
+    sort_type_of(&values)
+}
+
+/// The sort type of a column holding `values`, which are trimmed and not empty.
+pub(crate) fn sort_type_of<S: AsRef<str>>(values: &[S]) -> &'static str {
+    if values.is_empty() {
+        "text"
+    } else if values
+        .iter()
+        .all(|value| NUMBER_REGEX.is_match(value.as_ref()))
+    {
+        "number"
+    } else if values
+        .iter()
+        .all(|value| DATE_REGEX.is_match(value.as_ref()))
+    {
+        "date"
+    } else {
+        "text"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sort_type() {
+        assert_eq!(sort_type_of(&["1", "-2.5", "$1,200", "40%"]), "number");
+        assert_eq!(
+            sort_type_of(&["2024-01-31", "2024-02-01 09:30", "3/4/25"]),
+            "date"
+        );
+        assert_eq!(sort_type_of(&["12", "twelve"]), "text");
+        assert_eq!(sort_type_of(&["1,20"]), "text");
+        assert_eq!(sort_type_of::<&str>(&[]), "text");
+    }
+
+    #[test]
+    fn test_source_cells() {
+        assert_eq!(source_cells("| a || b |"), vec![" a ", "", " b "]);
+        assert_eq!(source_cells("a | | b"), vec!["a ", " ", " b"]);
+        assert_eq!(source_cells("| a \\| b ||"), vec![" a \\| b ", ""]);
+        assert_eq!(source_cells("| a |  "), vec![" a "]);
+    }
+}
@@ -0,0 +1,200 @@
+//! Task list rendering: interactive checkboxes mapped back to the source and
+//! extended task states.
+
+use comrak::nodes::{AstNode, NodeValue};
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+
+/// Render the task items the way `options` asks: with enabled checkboxes
+/// carrying the item's source line and its index among the `[ ]`/`[x]` tasks,
+/// counted from `first_index`, for [`crate::toggle_task`], and with the
+/// extended states of [`RenderOptions::extended_tasks`]. Task symbols that
+/// aren't a known state are put back as text, leaving a plain list item.
+///
+/// comrak always writes plain disabled checkboxes, so each task item is
+/// replaced by a raw node holding the opening tags, with the item content as
+/// its children and a raw closing tag last.
+pub(crate) fn render_tasks<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+    first_index: usize,
+) {
+    let tasks = root
+        .descendants()
+        .filter(|node| is_task(node))
+        .collect::<Vec<_>>();
+    let mut index = first_index;
+
+    for task in tasks {
+        let sourcepos = task.data.borrow().sourcepos;
+        let symbol = match task.data.borrow().value {
+            NodeValue::TaskItem(ref task) => task.symbol,
+            _ => None,
+        };
+
+        let mut open_html = String::from("<li");
+        let state = symbol.and_then(|symbol| options.task_state(symbol));
+        if let Some(state) = state {
+            let state = ast::escape_html(state);
+            open_html.push_str(&format!(
+                " class=\"task-{}\" data-task-state=\"{}\"",
+                state, state
+            ));
+        }
+        if options.sourcepos {
+            open_html.push_str(&format!(" data-sourcepos=\"{}\"", sourcepos));
+        }
+        open_html.push('>');
+
+        match symbol {
+            None | Some('x' | 'X') => {
+                open_html.push_str("<input type=\"checkbox\"");
+                if symbol.is_some() {
+                    open_html.push_str(" checked=\"\"");
+                }
+                match options.interactive_tasks {
+                    true => open_html.push_str(&format!(
+                        " data-task-line=\"{}\" data-task-index=\"{}\" /> ",
+                        sourcepos.start.line, index
+                    )),
+                    false => open_html.push_str(" disabled=\"\" /> "),
+                }
+                index += 1;
+            }
+            Some(_) if state.is_some() => {
+                open_html.push_str("<input type=\"checkbox\" disabled=\"\" /> ");
+            }
+            Some(symbol) => {
+                let text = format!("[{}] ", symbol);
+                let paragraph = task
+                    .first_child()
+                    .filter(|child| matches!(child.data.borrow().value, NodeValue::Paragraph));
+                if let Some(paragraph) = paragraph {
+                    paragraph.prepend(arena.alloc(NodeValue::Text(text.into()).into()));
+                }
+            }
+        }
+
+        let wrapper = arena.alloc(NodeValue::Raw(open_html).into());
+        wrapper.data.borrow_mut().sourcepos = sourcepos;
+        task.insert_before(wrapper);
+        for child in task.children().collect::<Vec<_>>() {
+            wrapper.append(child);
+        }
+        wrapper.append(arena.alloc(NodeValue::Raw("</li>\n".into()).into()));
+        task.detach();
+    }
+}
+
+/// Whether `options` changes how task items are rendered.
+pub(crate) fn renders_tasks(options: &RenderOptions) -> bool {
+    options.tasklist && (options.interactive_tasks || options.extended_tasks)
+}
+
+/// `markdown`, parsed into `root`, with the checkbox of the task at `index`
+/// flipped between `[ ]` and `[x]`, or `None` if there is no such task.
+/// Extended task states aren't counted, as in `data-task-index`.
+pub(crate) fn toggle_task<'a>(
+    root: &'a AstNode<'a>,
+    markdown: &str,
+    index: usize,
+) -> Option<String> {
+    let task = root
+        .descendants()
+        .filter(|node| is_checkbox_task(node))
+        .nth(index)?;
+    let start = task.data.borrow().sourcepos.start;
+
+    // The item starts at its list marker; the checkbox is the first `[` after it.
+    let line_start = markdown
+        .split_inclusive('\n')
+        .take(start.line - 1)
+        .map(str::len)
+        .sum::<usize>();
+    let marker = line_start + start.column - 1;
+    let line_end = markdown[marker..]
+        .find('\n')
+        .map_or(markdown.len(), |end| marker + end);
+    let symbol = marker + markdown[marker..line_end].find('[')? + 1;
+    if markdown.as_bytes().get(symbol + 1) != Some(&b']') {
+        return None;
+    }
+
+    let flipped = match markdown.as_bytes()[symbol] {
+        b' ' => "x",
+        _ => " ",
+    };
+    Some(format!(
+        "{}{}{}",
+        &markdown[..symbol],
+        flipped,
+        &markdown[symbol + 1..]
+    ))
+}
+
+fn is_task<'a>(node: &'a AstNode<'a>) -> bool {
+    matches!(node.data.borrow().value, NodeValue::TaskItem(..))
+}
+
+/// Whether `node` is a `[ ]` or `[x]` task, the tasks `data-task-index` counts.
+pub(crate) fn is_checkbox_task<'a>(node: &'a AstNode<'a>) -> bool {
+    matches!(
+        node.data.borrow().value,
+        NodeValue::TaskItem(ref task) if matches!(task.symbol, None | Some('x' | 'X'))
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{Arena, parse_document};
+
+    use crate::OPTIONS;
+
+    fn toggle(markdown: &str, index: usize) -> Option<String> {
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &OPTIONS);
+        toggle_task(root, markdown, index)
+    }
+
+    #[test]
+    fn test_toggle_task_skips_extended_states() {
+        let mut options = RenderOptions::new();
+        options.extended_tasks = true;
+        let markdown = "- [-] dropped\n- [ ] open\n";
+        let arena = Arena::new();
+        let root = parse_document(&arena, markdown, &options.comrak_options());
+
+        assert_eq!(
+            toggle_task(root, markdown, 0).unwrap(),
+            "- [-] dropped\n- [x] open\n"
+        );
+        assert_eq!(toggle_task(root, markdown, 1), None);
+    }
+
+    #[test]
+    fn test_toggle_task() {
+        let markdown =
+            "# [ ] Not a task\n\n- [ ] one\n- [x] two\n  1. [X] nested\n\n> * [ ] quoted\n";
+
+        assert_eq!(
+            toggle(markdown, 0).unwrap(),
+            "# [ ] Not a task\n\n- [x] one\n- [x] two\n  1. [X] nested\n\n> * [ ] quoted\n"
+        );
+        assert_eq!(
+            toggle(markdown, 1).unwrap(),
+            "# [ ] Not a task\n\n- [ ] one\n- [ ] two\n  1. [X] nested\n\n> * [ ] quoted\n"
+        );
+        assert_eq!(
+            toggle(markdown, 2).unwrap(),
+            "# [ ] Not a task\n\n- [ ] one\n- [x] two\n  1. [ ] nested\n\n> * [ ] quoted\n"
+        );
+        assert_eq!(
+            toggle(markdown, 3).unwrap(),
+            "# [ ] Not a task\n\n- [ ] one\n- [x] two\n  1. [X] nested\n\n> * [x] quoted\n"
+        );
+        assert_eq!(toggle(markdown, 4), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! GitHub-compatible heading slugs and table of contents generation.
+
+use comrak::adapters::{HeadingAdapter, HeadingMeta};
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ast::{self, Arena};
+use crate::render::Heading;
+
+/// Paragraph text that is replaced by the rendered table of contents.
+const TOC_PLACEHOLDER: &str = "[[toc]]";
+
+/// Generates unique heading slugs the way GitHub does.
+///
+/// Repeated slugs get `-1`, `-2`, ... appended, matching `github-slugger`.
+#[derive(Debug, Default)]
+pub(crate) struct Slugger {
+    occurrences: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut slug = base.clone();
+
+        while self.occurrences.contains_key(&slug) {
+            let count = self.occurrences.entry(base.clone()).or_default();
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// Lowercase `text`, drop punctuation and turn spaces into hyphens.
+fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A node in the table of contents tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct TocEntry {
+    pub(crate) level: u8,
+    pub(crate) text: String,
+    pub(crate) slug: String,
+    pub(crate) children: Vec<TocEntry>,
+}
+
+/// Nest `headings` into a tree. A heading becomes a child of the closest
+/// preceding heading with a lower level.
+pub(crate) fn build_toc(headings: &[Heading]) -> Vec<TocEntry> {
+    fn insert(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+        match entries.last_mut() {
+            Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+            _ => entries.push(entry),
+        }
+    }
+
+    let mut toc = Vec::new();
+    for heading in headings {
+        insert(
+            &mut toc,
+            TocEntry {
+                level: heading.level,
+                text: heading.text.clone(),
+                slug: heading.slug.clone(),
+                children: Vec::new(),
+            },
+        );
+    }
+
+    toc
+}
+
+/// Render the table of contents as a nested list inside a `<nav>`.
+pub(crate) fn render_toc(toc: &[TocEntry]) -> String {
+    fn render_list(output: &mut String, entries: &[TocEntry]) {
+        if entries.is_empty() {
+            return;
+        }
+
+        output.push_str("<ul>\n");
+        for entry in entries {
+            let _ = write!(
+                output,
+                "<li><a href=\"#{}\">{}</a>",
+                entry.slug,
+                ast::escape_html(&entry.text)
+            );
+            render_list(output, &entry.children);
+            output.push_str("</li>\n");
+        }
+        output.push_str("</ul>\n");
+    }
+
+    let mut output = String::from("<nav class=\"toc\">\n");
+    render_list(&mut output, toc);
+    output.push_str("</nav>\n");
+    output
+}
+
+/// Replace every paragraph consisting of just `[[toc]]` with the rendered table of contents.
+pub(crate) fn replace_placeholders<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    toc: &[TocEntry],
+) {
+    let placeholders = root
+        .descendants()
+        .filter(|node| is_placeholder(node))
+        .collect::<Vec<_>>();
+
+    if placeholders.is_empty() {
+        return;
+    }
+
+    let html = render_toc(toc);
+    for placeholder in placeholders {
+        let nav = arena.alloc(NodeValue::Raw(html.clone()).into());
+        nav.data.borrow_mut().sourcepos = placeholder.data.borrow().sourcepos;
+        placeholder.insert_before(nav);
+        placeholder.detach();
+    }
+}
+
+/// Whether `node` is a paragraph consisting of just the placeholder, which
+/// parses as a wikilink to `toc` when wikilinks are enabled.
+fn is_placeholder<'a>(node: &'a AstNode<'a>) -> bool {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return false;
+    }
+    if ast::text_content(node).trim() == TOC_PLACEHOLDER {
+        return true;
+    }
+
+    let mut children = node.children();
+    match (children.next(), children.next()) {
+        (Some(child), None) => {
+            matches!(child.data.borrow().value, NodeValue::WikiLink(ref link) if link.url == "toc")
+        }
+        _ => false,
+    }
+}
+
+/// Writes headings with precomputed slugs as their `id`.
+///
+/// comrak renders headings in document order, so the adapter hands out the
+/// slugs collected from the same tree one by one.
+pub(crate) struct SlugHeadingAdapter {
+    slugs: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl SlugHeadingAdapter {
+    pub(crate) fn new(slugs: Vec<String>) -> Self {
+        Self {
+            slugs,
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl HeadingAdapter for SlugHeadingAdapter {
+    fn enter(
+        &self,
+        output: &mut dyn Write,
+        heading: &HeadingMeta,
+        sourcepos: Option<Sourcepos>,
+    ) -> fmt::Result {
+        write!(output, "<h{}", heading.level)?;
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        if let Some(slug) = self.slugs.get(index) {
+            write!(output, " id=\"{}\"", slug)?;
+        }
+        if let Some(sourcepos) = sourcepos {
+            write!(output, " data-sourcepos=\"{}\"", sourcepos)?;
+        }
+
+        output.write_str(">")
+    }
+
+    fn exit(&self, output: &mut dyn Write, heading: &HeadingMeta) -> fmt::Result {
+        write!(output, "</h{}>", heading.level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slugs_match_github() {
+        let mut slugger = Slugger::default();
+
+        assert_eq!(slugger.slug("Installation"), "installation");
+        assert_eq!(slugger.slug("What's new in v2.0?"), "whats-new-in-v20");
+        assert_eq!(
+            slugger.slug("snake_case & kebab-case"),
+            "snake_case--kebab-case"
+        );
+        assert_eq!(slugger.slug("Überblick"), "überblick");
+        assert_eq!(slugger.slug("Installation"), "installation-1");
+        assert_eq!(slugger.slug("Installation"), "installation-2");
+        assert_eq!(slugger.slug("Installation 1"), "installation-1-1");
+    }
+
+    #[test]
+    fn test_build_toc_nests_by_level() {
+        let heading = |level, text: &str| Heading {
+            level,
+            text: text.into(),
+            slug: text.to_lowercase(),
+            line: 0,
+        };
+        let toc = build_toc(&[
+            heading(2, "A"),
+            heading(3, "B"),
+            heading(4, "C"),
+            heading(3, "D"),
+            heading(1, "E"),
+        ]);
+
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].slug, "a");
+        assert_eq!(
+            toc[0]
+                .children
+                .iter()
+                .map(|e| e.slug.as_str())
+                .collect::<Vec<_>>(),
+            vec!["b", "d"]
+        );
+        assert_eq!(toc[0].children[0].children[0].slug, "c");
+        assert_eq!(toc[1].slug, "e");
+    }
+}
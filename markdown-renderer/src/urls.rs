@@ -0,0 +1,192 @@
+//! Rewriting of link and image destinations: base URLs, `.md` extensions and
+//! a user-supplied JS callback.
+
+use comrak::nodes::{AstNode, NodeValue};
+use wasm_bindgen::JsValue;
+
+use crate::RenderOptions;
+
+/// Whether `options` asks for any destination rewriting.
+pub(crate) fn rewrites_urls(options: &RenderOptions) -> bool {
+    options.link_base_url.is_some()
+        || options.image_base_url.is_some()
+        || options.md_link_extension.is_some()
+        || options.url_rewriter().is_some()
+}
+
+/// Rewrite the destination of every link, wikilink and image in the document.
+///
+/// This runs on the AST, so the rewritten URLs still go through the
+/// sanitizer's URL scheme check.
+pub(crate) fn rewrite_urls<'a>(root: &'a AstNode<'a>, options: &RenderOptions) {
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        let (kind, url) = match ast.value {
+            NodeValue::Link(ref mut link) => ("link", &mut link.url),
+            NodeValue::WikiLink(ref mut link) => ("link", &mut link.url),
+            NodeValue::Image(ref mut link) => ("image", &mut link.url),
+            _ => continue,
+        };
+
+        *url = rewrite_url(kind, url, options).into();
+    }
+}
+
+/// Rewrite one destination of the given `kind`, `"link"` or `"image"`.
+pub(crate) fn rewrite_url(kind: &str, url: &str, options: &RenderOptions) -> String {
+    let mut url = url.to_string();
+
+    if is_relative(&url) {
+        if kind == "link"
+            && let Some(extension) = &options.md_link_extension
+        {
+            url = replace_md_extension(&url, extension);
+        }
+
+        let base = match kind {
+            "image" => &options.image_base_url,
+            _ => &options.link_base_url,
+        };
+        if let Some(base) = base {
+            url = join(base, &url);
+        }
+    }
+
+    if let Some(rewriter) = options.url_rewriter() {
+        let rewritten = rewriter.call2(&JsValue::NULL, &kind.into(), &url.as_str().into());
+        if let Some(rewritten) = rewritten.ok().and_then(|value| value.as_string()) {
+            url = rewritten;
+        }
+    }
+
+    url
+}
+
+/// Relative destinations have no scheme and don't start with `/`, `#` or `?`.
+fn is_relative(url: &str) -> bool {
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && !scheme.contains(['/', '?', '#'])
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+
+    !url.is_empty() && !has_scheme && !url.starts_with(['/', '#', '?'])
+}
+
+/// Replace a trailing `.md` on the path of `url`, keeping any query and fragment.
+fn replace_md_extension(url: &str, extension: &str) -> String {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(path_end);
+
+    match path.strip_suffix(".md") {
+        Some(stem) if !stem.is_empty() && !stem.ends_with('/') => {
+            format!("{}{}{}", stem, extension, suffix)
+        }
+        _ => url.into(),
+    }
+}
+
+/// Resolve the relative reference `url` against `base` as in RFC 3986, section
+/// 5.2: the last segment of the base path is replaced, `.` and `..` segments
+/// are removed, and the base query and fragment are dropped.
+fn join(base: &str, url: &str) -> String {
+    let base = &base[..base.find(['?', '#']).unwrap_or(base.len())];
+    let path_start = match base.find("://") {
+        Some(scheme_end) => {
+            let authority = scheme_end + 3;
+            base[authority..]
+                .find('/')
+                .map_or(base.len(), |path| authority + path)
+        }
+        None => 0,
+    };
+    let (origin, base_path) = base.split_at(path_start);
+
+    let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    let merged = match (origin.is_empty(), base_path.rfind('/')) {
+        (false, None) => format!("/{}", path),
+        (_, Some(last)) => format!("{}{}", &base_path[..=last], path),
+        (true, None) => path.to_string(),
+    };
+
+    format!("{}{}{}", origin, remove_dot_segments(&merged), suffix)
+}
+
+/// Remove the `.` and `..` segments of `path`. `..` segments above the start of
+/// a relative path are kept, and dropped at the root of an absolute one.
+fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let segments = path.split('/').collect::<Vec<_>>();
+    let last = segments.len() - 1;
+    let mut output: Vec<&str> = Vec::new();
+
+    for (i, segment) in segments.into_iter().enumerate() {
+        match segment {
+            "." => {}
+            ".." => {
+                if output.last().is_some_and(|s| !s.is_empty() && *s != "..") {
+                    output.pop();
+                } else if !absolute {
+                    output.push("..");
+                }
+            }
+            _ => output.push(segment),
+        }
+        // A trailing `.` or `..` still names a directory.
+        if i == last && matches!(segment, "." | "..") {
+            output.push("");
+        }
+    }
+
+    output.join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_url() {
+        let mut options = RenderOptions::new();
+        options.link_base_url = Some("https://example.com/docs/".into());
+        options.image_base_url = Some("https://cdn.example.com/".into());
+        options.md_link_extension = Some(".html".into());
+
+        let link = |url| rewrite_url("link", url, &options);
+        assert_eq!(link("other.md#x"), "https://example.com/docs/other.html#x");
+        assert_eq!(
+            link("./guide/intro.md"),
+            "https://example.com/docs/guide/intro.html"
+        );
+        assert_eq!(link("../up.txt"), "https://example.com/up.txt");
+        assert_eq!(
+            link("a/./b/../c.txt?q=1#f"),
+            "https://example.com/docs/a/c.txt?q=1#f"
+        );
+        assert_eq!(link("../../../x"), "https://example.com/x");
+        assert_eq!(link("#section"), "#section");
+        assert_eq!(link("/root.md"), "/root.md");
+        assert_eq!(link("https://other.com/a.md"), "https://other.com/a.md");
+        assert_eq!(link("mailto:me@example.com"), "mailto:me@example.com");
+
+        let image = |url| rewrite_url("image", url, &options);
+        assert_eq!(image("./img/a.png"), "https://cdn.example.com/img/a.png");
+        assert_eq!(image("notes.md"), "https://cdn.example.com/notes.md");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join("https://x.com/docs/index.html?v=2", "img.png"),
+            "https://x.com/docs/img.png"
+        );
+        assert_eq!(join("https://x.com/docs", "a/"), "https://x.com/a/");
+        assert_eq!(join("https://x.com", "a"), "https://x.com/a");
+        assert_eq!(join("https://x.com/a/b/", ".."), "https://x.com/a/");
+        assert_eq!(join("/static/", "../img.png"), "/img.png");
+        assert_eq!(join("assets/", "../img.png"), "img.png");
+        assert_eq!(join("assets/", "../../img.png"), "../img.png");
+    }
+}
@@ -0,0 +1,77 @@
+//! `[[Page Name]]` and `[[Page Name|label]]` wikilinks.
+
+use comrak::nodes::{AstNode, NodeValue};
+use wasm_bindgen::JsValue;
+
+use crate::RenderOptions;
+use crate::ast::{self, Arena};
+use crate::options::WikiLinkResolver;
+
+/// Class of the element rendered for a wikilink whose page doesn't resolve.
+const MISSING_CLASS: &str = "wikilink-missing";
+
+/// Resolve every wikilink in the document and return the referenced page
+/// names in order of first use.
+///
+/// Resolved links point at the resolver's URL; without a resolver they keep
+/// pointing at the page name. Links to missing pages become an `<a>` without
+/// `href` carrying the [`MISSING_CLASS`] class and the page name as title.
+pub(crate) fn resolve_wikilinks<'a>(
+    arena: &'a Arena<'a>,
+    root: &'a AstNode<'a>,
+    options: &RenderOptions,
+) -> Vec<String> {
+    let mut pages: Vec<String> = Vec::new();
+
+    for node in root.descendants().collect::<Vec<_>>() {
+        let page = match node.data.borrow().value {
+            NodeValue::WikiLink(ref link) => link.url.to_string(),
+            _ => continue,
+        };
+
+        let url = match options.wikilink_resolver() {
+            Some(resolver) => resolve(resolver, &page),
+            None => Some(page.clone()),
+        };
+        match url {
+            Some(url) => {
+                if let NodeValue::WikiLink(ref mut link) = node.data.borrow_mut().value {
+                    link.url = url.into();
+                }
+            }
+            None => mark_missing(arena, node, &page),
+        }
+
+        if !pages.contains(&page) {
+            pages.push(page);
+        }
+    }
+
+    pages
+}
+
+fn resolve(resolver: &WikiLinkResolver, page: &str) -> Option<String> {
+    match resolver {
+        WikiLinkResolver::Callback(callback) => callback
+            .0
+            .call1(&JsValue::NULL, &page.into())
+            .ok()
+            .and_then(|url| url.as_string()),
+        WikiLinkResolver::Pages(pages) => pages.get(page).cloned(),
+    }
+}
+
+/// Replace the wikilink `node` with its label inside a missing-page anchor.
+fn mark_missing<'a>(arena: &'a Arena<'a>, node: &'a AstNode<'a>, page: &str) {
+    let open = format!(
+        "<a class=\"{}\" title=\"{}\">",
+        MISSING_CLASS,
+        ast::escape_html(page)
+    );
+    node.insert_before(arena.alloc(NodeValue::Raw(open).into()));
+    for child in node.children().collect::<Vec<_>>() {
+        node.insert_before(child);
+    }
+    node.insert_before(arena.alloc(NodeValue::Raw("</a>".into()).into()));
+    node.detach();
+}